@@ -1,9 +1,10 @@
+use bytes::Bytes;
 use const_format::formatcp;
 use reqwest::Url;
 
 use crate::{
-    errors::ApiError, objects::UserStats, rate_limit::RateLimit, AnimeScheduleClient, API_URL,
-    RUNTIME,
+    api_request::deserialize, errors::ApiError, objects::UserStats, rate_limit::RateLimit,
+    AnimeScheduleClient, API_URL,
 };
 
 const API_ACCOUNT_AVATAR: &str = formatcp!("{API_URL}/users/{{userId}}/avatar");
@@ -56,29 +57,84 @@ impl AccountApiGet {
     }
 }
 
+/// A downloaded image
+#[derive(Debug, Clone)]
+pub struct Image {
+    /// the image's mime type (e.g. `image/png`), if the server sent it
+    pub content_type: Option<String>,
+    /// the raw image data
+    pub data: Bytes,
+}
+
+impl Image {
+    /// The mime type without any parameters, e.g. `image/png` for `image/png; charset=binary`
+    pub fn mime_type(&self) -> Option<&str> {
+        let content_type = self.content_type.as_deref()?;
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+        (!mime.is_empty()).then_some(mime)
+    }
+
+    /// The usual file extension of the image's mime type, without the dot. None if it isn't a known image type
+    pub fn extension(&self) -> Option<&'static str> {
+        let extension = match &*self.mime_type()?.to_ascii_lowercase() {
+            "image/png" => "png",
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => "jpg",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            "image/avif" => "avif",
+            "image/svg+xml" => "svg",
+            "image/bmp" => "bmp",
+            "image/x-icon" | "image/vnd.microsoft.icon" => "ico",
+            _ => return None,
+        };
+
+        Some(extension)
+    }
+}
+
 pub struct AccountApiAvatar {
     client: AnimeScheduleClient,
     user_id: Option<String>,
 }
 
 impl AccountApiAvatar {
+    /// Set the user id. Defaults to the authenticated user.
     pub fn user_id(mut self, user_id: &str) -> Self {
         self.user_id = Some(user_id.to_owned());
         self
     }
 
     pub async fn send(mut self) -> Result<(RateLimit, Url), ApiError> {
-        let Some(user_id) = self.user_id else {
-            return Err(ApiError::UserId);
+        let user_id = match self.user_id {
+            Some(user_id) => user_id,
+            None => self.client.current_user_id().await?,
         };
 
         let url = API_ACCOUNT_AVATAR.replace("{userId}", &user_id);
 
-        self.client.http.get(url, false).await
+        let (limit, body) = self.client.http.get_raw(url, false).await?;
+
+        Ok((limit, parse_url(&body)?))
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, Url), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+
+    /// Fetch the avatar image itself
+    pub async fn download(self) -> Result<(RateLimit, Image), ApiError> {
+        let http = self.client.http.clone();
+        let (limit, url) = self.send().await?;
+
+        let (content_type, data) = http.download(url).await?;
+
+        Ok((limit, Image { content_type, data }))
+    }
+
+    /// Fetch the avatar image itself
+    pub fn download_blocking(self) -> Result<(RateLimit, Image), ApiError> {
+        self.client.runtime().block_on(self.download())
     }
 }
 
@@ -88,23 +144,42 @@ pub struct AccountApiBanner {
 }
 
 impl AccountApiBanner {
+    /// Set the user id. Defaults to the authenticated user.
     pub fn user_id(mut self, user_id: &str) -> Self {
         self.user_id = Some(user_id.to_owned());
         self
     }
 
     pub async fn send(mut self) -> Result<(RateLimit, Url), ApiError> {
-        let Some(user_id) = self.user_id else {
-            return Err(ApiError::UserId);
+        let user_id = match self.user_id {
+            Some(user_id) => user_id,
+            None => self.client.current_user_id().await?,
         };
 
         let url = API_ACCOUNT_BANNER.replace("{userId}", &user_id);
 
-        self.client.http.get(url, false).await
+        let (limit, body) = self.client.http.get_raw(url, false).await?;
+
+        Ok((limit, parse_url(&body)?))
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, Url), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+
+    /// Fetch the banner image itself
+    pub async fn download(self) -> Result<(RateLimit, Image), ApiError> {
+        let http = self.client.http.clone();
+        let (limit, url) = self.send().await?;
+
+        let (content_type, data) = http.download(url).await?;
+
+        Ok((limit, Image { content_type, data }))
+    }
+
+    /// Fetch the banner image itself
+    pub fn download_blocking(self) -> Result<(RateLimit, Image), ApiError> {
+        self.client.runtime().block_on(self.download())
     }
 }
 
@@ -114,14 +189,16 @@ pub struct AccountApiStats {
 }
 
 impl AccountApiStats {
+    /// Set the user id. Defaults to the authenticated user.
     pub fn user_id(mut self, user_id: &str) -> Self {
         self.user_id = Some(user_id.to_owned());
         self
     }
 
     pub async fn send(mut self) -> Result<(RateLimit, UserStats), ApiError> {
-        let Some(user_id) = self.user_id else {
-            return Err(ApiError::UserId);
+        let user_id = match self.user_id {
+            Some(user_id) => user_id,
+            None => self.client.current_user_id().await?,
         };
 
         let url = API_ACCOUNT_STATS.replace("{userId}", &user_id);
@@ -130,6 +207,33 @@ impl AccountApiStats {
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, UserStats), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+}
+
+/// The avatar and banner endpoints' url, whether it's sent as a json string or as bare text
+fn parse_url(body: &str) -> Result<Url, ApiError> {
+    let body = body.trim();
+
+    if body.starts_with('"') {
+        return deserialize(body);
+    }
+
+    Ok(Url::parse(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bare_and_quoted_urls() {
+        let url = "https://img.animeschedule.net/production/assets/public/img/avatars/abc.png";
+
+        assert_eq!(parse_url(url).unwrap().as_str(), url);
+        assert_eq!(parse_url(&format!("{url}\n")).unwrap().as_str(), url);
+        assert_eq!(parse_url(&format!("\"{url}\"")).unwrap().as_str(), url);
+
+        assert!(parse_url("not a url").is_err());
     }
 }
@@ -1,14 +1,28 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Debug,
+    ops::Deref,
+};
+
 use const_format::formatcp;
-use serde::Serialize;
+use futures_util::{stream, Stream};
+use http::StatusCode;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+#[cfg(feature = "borrowed")]
+use crate::objects::RawJson;
 use crate::{
-    errors::ApiError,
+    batch::{self, BatchMode},
+    errors::{ApiError, BuilderError},
     objects::{
         AirStatusQuery, Anime, AnimePage, MatchType, SeasonQuery, SortingType, StreamsQuery,
     },
     rate_limit::RateLimit,
-    AnimeScheduleClient, API_URL, RUNTIME,
+    response::ResponseMeta,
+    utils::truncate_chars,
+    AnimeScheduleClient, API_URL,
 };
 
 const API_ANIME: &str = formatcp!("{API_URL}/anime");
@@ -24,44 +38,24 @@ impl AnimeApi {
     }
 
     pub fn get(&self) -> AnimeGet {
-        AnimeGet {
-            client: self.client.clone(),
-            page: None,
-            q: None,
-            mt: None,
-            st: None,
-            genres: None,
-            genres_exclude: None,
-            studios: None,
-            studios_exclude: None,
-            sources: None,
-            sources_exclude: None,
-            media_types: None,
-            media_types_exclude: None,
-            years: None,
-            years_exclude: None,
-            seasons: None,
-            seasons_exclude: None,
-            airing_statuses: None,
-            airing_statuses_exclude: None,
-            duration: None,
-            episodes: None,
-            streams: None,
-            streams_exclude: None,
-            mal_ids: None,
-            anilist_ids: None,
-            anidb_ids: None,
-        }
+        AnimeGet::new(self.client.clone(), AnimeSearch::default())
+    }
+
+    /// Run a search, e.g. one parsed from a shared url with [`AnimeSearch::from_query_str()`]
+    pub fn search(&self, search: AnimeSearch) -> AnimeGet {
+        AnimeGet::new(self.client.clone(), search)
     }
 }
 
+/// The filters of an anime search. This is what's sent as the query string.
+///
+/// It can be turned into a query string and back, e.g. for shareable search urls.
+///
+/// Duplicate values in the list filters are dropped, keeping the first occurrence.
 #[skip_serializing_none]
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "kebab-case")]
-pub struct AnimeGet {
-    #[serde(skip)]
-    client: AnimeScheduleClient,
-
+pub struct AnimeSearch {
     /// The number of the page of the anime array being requested. Defaults to 1.
     page: Option<u64>,
     /// Filter by text. Applies to an anime's names. Failing that it tries genres, studios,
@@ -131,7 +125,230 @@ pub struct AnimeGet {
     anidb_ids: Option<Vec<u64>>,
 }
 
+/// Build an anime search. Fetches a page of anime matching the filters.
+pub struct AnimeGet {
+    client: AnimeScheduleClient,
+    search: AnimeSearch,
+    /// whether sending a search without filters is an error
+    require_filters: bool,
+}
+
+impl AnimeSearch {
+    /// The search as a query string, e.g. `genres=action&years=2024`
+    pub fn to_query_string(&self) -> String {
+        serde_qs::to_string(self).unwrap()
+    }
+
+    /// Whether any filter or text is set. The page, match type, and sorting type don't count.
+    pub fn has_filters(&self) -> bool {
+        // destructured so a new filter can't be forgotten here
+        let AnimeSearch {
+            page: _,
+            q,
+            mt: _,
+            st: _,
+            genres,
+            genres_exclude,
+            studios,
+            studios_exclude,
+            sources,
+            sources_exclude,
+            media_types,
+            media_types_exclude,
+            years,
+            years_exclude,
+            seasons,
+            seasons_exclude,
+            airing_statuses,
+            airing_statuses_exclude,
+            duration,
+            episodes,
+            streams,
+            streams_exclude,
+            mal_ids,
+            anilist_ids,
+            anidb_ids,
+        } = self;
+
+        let lists = [
+            genres.as_ref().map(Vec::len),
+            genres_exclude.as_ref().map(Vec::len),
+            studios.as_ref().map(Vec::len),
+            studios_exclude.as_ref().map(Vec::len),
+            sources.as_ref().map(Vec::len),
+            sources_exclude.as_ref().map(Vec::len),
+            media_types.as_ref().map(Vec::len),
+            media_types_exclude.as_ref().map(Vec::len),
+            years.as_ref().map(Vec::len),
+            years_exclude.as_ref().map(Vec::len),
+            seasons.as_ref().map(Vec::len),
+            seasons_exclude.as_ref().map(Vec::len),
+            airing_statuses.as_ref().map(Vec::len),
+            airing_statuses_exclude.as_ref().map(Vec::len),
+            streams.as_ref().map(Vec::len),
+            streams_exclude.as_ref().map(Vec::len),
+            mal_ids.as_ref().map(Vec::len),
+            anilist_ids.as_ref().map(Vec::len),
+            anidb_ids.as_ref().map(Vec::len),
+        ];
+
+        let texts = [q, duration, episodes];
+
+        lists.into_iter().any(|len| len.unwrap_or_default() > 0)
+            || texts
+                .into_iter()
+                .any(|text| text.as_deref().is_some_and(|text| !text.trim().is_empty()))
+    }
+
+    /// Check for filters which can't match anything, i.e. a value which is both included and excluded.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        check_overlap("genres", &self.genres, &self.genres_exclude)?;
+        check_overlap("studios", &self.studios, &self.studios_exclude)?;
+        check_overlap("sources", &self.sources, &self.sources_exclude)?;
+        check_overlap("media-types", &self.media_types, &self.media_types_exclude)?;
+        check_overlap("years", &self.years, &self.years_exclude)?;
+        check_overlap("seasons", &self.seasons, &self.seasons_exclude)?;
+        check_overlap(
+            "airing-statuses",
+            &self.airing_statuses,
+            &self.airing_statuses_exclude,
+        )?;
+        check_overlap("streams", &self.streams, &self.streams_exclude)?;
+
+        Ok(())
+    }
+
+    /// Parse a query string made by [`Self::to_query_string()`] (or copied from a search url) back into a search.
+    /// A leading `?` is allowed.
+    pub fn from_query_str(query: &str) -> Result<Self, ApiError> {
+        let mut url = Url::parse(API_ANIME).unwrap();
+        url.set_query(Some(query.trim_start_matches('?')));
+
+        let mut search = Self::default();
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "page" => search.page = Some(parse(&key, &value)?),
+                "q" => search.q = Some(truncate_chars(&value, 200)),
+                "mt" => search.mt = Some(parse(&key, &value)?),
+                "st" => search.st = Some(parse(&key, &value)?),
+                "genres" => push(&mut search.genres, value.into_owned()),
+                "genres-exclude" => push(&mut search.genres_exclude, value.into_owned()),
+                "studios" => push(&mut search.studios, value.into_owned()),
+                "studios-exclude" => push(&mut search.studios_exclude, value.into_owned()),
+                "sources" => push(&mut search.sources, value.into_owned()),
+                "sources-exclude" => push(&mut search.sources_exclude, value.into_owned()),
+                "media-types" => push(&mut search.media_types, value.into_owned()),
+                "media-types-exclude" => push(&mut search.media_types_exclude, value.into_owned()),
+                "years" => push(&mut search.years, parse(&key, &value)?),
+                "years-exclude" => push(&mut search.years_exclude, parse(&key, &value)?),
+                "seasons" => push(&mut search.seasons, parse(&key, &value)?),
+                "seasons-exclude" => push(&mut search.seasons_exclude, parse(&key, &value)?),
+                "airing-statuses" => push(&mut search.airing_statuses, parse(&key, &value)?),
+                "airing-statuses-exclude" => {
+                    push(&mut search.airing_statuses_exclude, parse(&key, &value)?)
+                }
+                "duration" => search.duration = Some(query_range(&key, &value)?),
+                "episodes" => search.episodes = Some(query_range(&key, &value)?),
+                "streams" => push(&mut search.streams, parse(&key, &value)?),
+                "streams-exclude" => push(&mut search.streams_exclude, parse(&key, &value)?),
+                "mal-ids" => push(&mut search.mal_ids, parse(&key, &value)?),
+                "anilist-ids" => push(&mut search.anilist_ids, parse(&key, &value)?),
+                "anidb-ids" => push(&mut search.anidb_ids, parse(&key, &value)?),
+                _ => return Err(ApiError::Query(format!("unknown parameter '{key}'"))),
+            }
+        }
+
+        Ok(search)
+    }
+}
+
+fn check_overlap<T: PartialEq + Debug>(
+    name: &str,
+    include: &Option<Vec<T>>,
+    exclude: &Option<Vec<T>>,
+) -> Result<(), ApiError> {
+    let (Some(include), Some(exclude)) = (include, exclude) else {
+        return Ok(());
+    };
+
+    match include.iter().find(|value| exclude.contains(value)) {
+        Some(value) => Err(ApiError::Validation(format!(
+            "{value:?} is both in {name} and {name}-exclude"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// a range in the api's min-max format, where an unbounded max is ∞
+fn format_range(min: u64, max: Option<u64>) -> String {
+    match max {
+        Some(max) => format!("{min}-{}", max.max(min)),
+        None => format!("{min}-∞"),
+    }
+}
+
+/// checks a range is in the api's min-max format
+fn check_range(range: &str) -> Result<String, BuilderError> {
+    let invalid = || BuilderError::Range(range.to_owned());
+
+    let (min, max) = range.trim().split_once('-').ok_or_else(invalid)?;
+    let min = min.trim().parse::<u64>().map_err(|_| invalid())?;
+
+    let max = match max.trim() {
+        "∞" => None,
+        max => Some(max.parse::<u64>().map_err(|_| invalid())?),
+    };
+
+    if max.is_some_and(|max| max < min) {
+        return Err(invalid());
+    }
+
+    Ok(format_range(min, max))
+}
+
+/// removes duplicates, keeping the first occurrence. the lists are short, so a linear search is fine
+fn unique<T: PartialEq>(items: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut unique = Vec::new();
+
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+
+    unique
+}
+
+/// appends to a list, ignoring duplicates
+fn push<T: PartialEq>(list: &mut Option<Vec<T>>, value: T) {
+    let list = list.get_or_insert_with(Vec::new);
+
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+fn query_range(key: &str, value: &str) -> Result<String, ApiError> {
+    check_range(value).map_err(|_| ApiError::Query(format!("invalid value '{value}' for '{key}'")))
+}
+
+/// a query string value. numbers are parsed as json, and enums from their plain string
+fn parse<T: DeserializeOwned>(key: &str, value: &str) -> Result<T, ApiError> {
+    serde_json::from_str(value)
+        .or_else(|_| serde_json::from_value(serde_json::Value::String(value.to_owned())))
+        .map_err(|_| ApiError::Query(format!("invalid value '{value}' for '{key}'")))
+}
+
 impl AnimeGet {
+    fn new(client: AnimeScheduleClient, search: AnimeSearch) -> Self {
+        Self {
+            client,
+            search,
+            require_filters: false,
+        }
+    }
+
     /// Fetch the data of a specific anime
     pub fn slug(&self, slug: &str) -> AnimeSlug {
         AnimeSlug {
@@ -140,81 +357,105 @@ impl AnimeGet {
         }
     }
 
-    /// Fetches a paginated array of anime. Each page can contain up to 18 anime. Can be filtered further by using
+    /// Fetch the data of multiple specific anime
+    pub fn slugs<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        slugs: I,
+        mode: BatchMode,
+    ) -> AnimeSlugs {
+        AnimeSlugs {
+            client: self.client.clone(),
+            slugs: slugs.into_iter().map(|s| s.to_owned()).collect(),
+            mode,
+        }
+    }
+
+    /// Fetches a paginated array of anime. Each page can contain up to [ANIME_PER_PAGE](crate::objects::ANIME_PER_PAGE)
+    /// anime. Can be filtered further by using
     /// the optional parameters. Contains the page number and
     /// amount of anime that match the parameters.
     pub fn page(mut self, page: u64) -> Self {
-        self.page = Some(page.clamp(1, u64::MAX));
+        self.search.page = Some(page.clamp(1, u64::MAX));
         self
     }
 
     /// Filter by text. Applies to an anime's names. Failing that it tries genres, studios, sources and media types.
     /// Maximum length is 200.
+    ///
+    /// The api treats this as a single free text string. It has no operators or multiple term support.
     pub fn q(mut self, q: &str) -> Self {
-        let mut q = q.to_owned();
-        q.truncate(200);
+        self.search.q = Some(truncate_chars(q, 200));
+        self
+    }
+
+    /// Append a term to the text filter, separated by a space. The whole text is still limited to 200.
+    pub fn q_append(mut self, term: &str) -> Self {
+        let q = match self.search.q.take() {
+            Some(q) if !q.is_empty() => format!("{q} {term}"),
+            _ => term.to_owned(),
+        };
 
-        self.q = Some(q);
+        self.search.q = Some(truncate_chars(&q, 200));
         self
     }
 
     /// The filter match type you want to use. Valid values are any and all. Any searches for anime that match any of
     /// the filters. All searches for ones that match all of the filters. Defaults to all.
     pub fn mt(mut self, mt: MatchType) -> Self {
-        self.mt = Some(mt);
+        self.search.mt = Some(mt);
         self
     }
 
     /// The sorting type you want to use. Valid values are popularity, score, alphabetic and releaseDate. Defaults
     /// to popularity.
     pub fn st(mut self, st: SortingType) -> Self {
-        self.st = Some(st);
+        self.search.st = Some(st);
         self
     }
 
     /// Filter by genre. Requires the genre's route/slug. For multiple genres add another genres query for each genre.
     pub fn genres<'a, I: IntoIterator<Item = &'a str>>(mut self, genres: I) -> Self {
-        self.genres = Some(genres.into_iter().map(|s| s.to_owned()).collect());
+        self.search.genres = Some(unique(genres.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Exclude a genre from the search. Requires the genre's route/slug. For multiple genres add another genres-exclude
     /// query for each genre.
     pub fn genres_exclude<'a, I: IntoIterator<Item = &'a str>>(mut self, genres: I) -> Self {
-        self.genres_exclude = Some(genres.into_iter().map(|s| s.to_owned()).collect());
+        self.search.genres_exclude = Some(unique(genres.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Filter by genre. Requires the studio's route/slug. For multiple studios add another studios query for each studio.
     pub fn studios<'a, I: IntoIterator<Item = &'a str>>(mut self, studios: I) -> Self {
-        self.studios = Some(studios.into_iter().map(|s| s.to_owned()).collect());
+        self.search.studios = Some(unique(studios.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Exclude a studio from the search. Requires the studio's route/slug. For multiple studios add another studios-exclude
     /// query for each studio.
     pub fn studios_exclude<'a, I: IntoIterator<Item = &'a str>>(mut self, studios: I) -> Self {
-        self.studios_exclude = Some(studios.into_iter().map(|s| s.to_owned()).collect());
+        self.search.studios_exclude = Some(unique(studios.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Filter by genre. Requires the source's route/slug. For multiple sources add another sources query for each source.
     pub fn sources<'a, I: IntoIterator<Item = &'a str>>(mut self, sources: I) -> Self {
-        self.sources = Some(sources.into_iter().map(|s| s.to_owned()).collect());
+        self.search.sources = Some(unique(sources.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Exclude a source from the search. Requires the source's route/slug. For multiple sources add another sources-exclude
     /// query for each source.
     pub fn sources_exclude<'a, I: IntoIterator<Item = &'a str>>(mut self, sources: I) -> Self {
-        self.sources_exclude = Some(sources.into_iter().map(|s| s.to_owned()).collect());
+        self.search.sources_exclude = Some(unique(sources.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Filter by genre. Requires the media type's route/slug. For multiple media types add another media-types query for each
     /// media-type.
     pub fn media_types<'a, I: IntoIterator<Item = &'a str>>(mut self, media_types: I) -> Self {
-        self.media_types = Some(media_types.into_iter().map(|s| s.to_owned()).collect());
+        self.search.media_types = Some(unique(media_types.into_iter().map(|s| s.to_owned())));
         self
     }
 
@@ -224,38 +465,39 @@ impl AnimeGet {
         mut self,
         media_types: I,
     ) -> Self {
-        self.media_types_exclude = Some(media_types.into_iter().map(|s| s.to_owned()).collect());
+        self.search.media_types_exclude =
+            Some(unique(media_types.into_iter().map(|s| s.to_owned())));
         self
     }
 
     /// Filter by year. For multiple years add another years query for each year.
     pub fn years<I: IntoIterator<Item = u16>>(mut self, years: I) -> Self {
-        self.years = Some(years.into_iter().collect());
+        self.search.years = Some(unique(years));
         self
     }
 
     /// Exclude a year from the search. For multiple years add another years-exclude query for each year.
     pub fn years_exclude<I: IntoIterator<Item = u16>>(mut self, years: I) -> Self {
-        self.years_exclude = Some(years.into_iter().collect());
+        self.search.years_exclude = Some(unique(years));
         self
     }
 
     /// Filter by calendar season. For multiple seasons add another seasons query for each season.
     pub fn seasons<I: IntoIterator<Item = SeasonQuery>>(mut self, seasons: I) -> Self {
-        self.seasons = Some(seasons.into_iter().collect());
+        self.search.seasons = Some(unique(seasons));
         self
     }
 
     /// Exclude a calendar season from the search. For multiple seasons add another seasons-exclude query for each season.
     pub fn seasons_exclude<I: IntoIterator<Item = SeasonQuery>>(mut self, seasons: I) -> Self {
-        self.seasons_exclude = Some(seasons.into_iter().collect());
+        self.search.seasons_exclude = Some(unique(seasons));
         self
     }
 
     /// Filter by airing status. For multiple airing statuses add another airing-statuses query for each airing status.
     /// Valid values are finished, ongoing and upcoming.
     pub fn airing_statuses<I: IntoIterator<Item = AirStatusQuery>>(mut self, seasons: I) -> Self {
-        self.airing_statuses = Some(seasons.into_iter().collect());
+        self.search.airing_statuses = Some(unique(seasons));
         self
     }
 
@@ -265,67 +507,373 @@ impl AnimeGet {
         mut self,
         seasons: I,
     ) -> Self {
-        self.airing_statuses_exclude = Some(seasons.into_iter().collect());
+        self.search.airing_statuses_exclude = Some(unique(seasons));
         self
     }
 
-    /// Filter by episode duration (in minutes.) Format is 0-∞.
-    pub fn duration(mut self, duration: &str) -> Self {
-        self.duration = Some(duration.to_owned());
+    /// Filter by episode duration (in minutes.) Format is 0-∞. See [`Self::duration_range()`] for a typed version.
+    pub fn duration(mut self, duration: &str) -> Result<Self, BuilderError> {
+        self.search.duration = Some(check_range(duration)?);
+        Ok(self)
+    }
+
+    /// Filter by episode duration (in minutes.) No max means no upper limit.
+    pub fn duration_range(mut self, min: u64, max: Option<u64>) -> Self {
+        self.search.duration = Some(format_range(min, max));
         self
     }
 
-    /// Filter by episode count. Format is 0-∞.
-    pub fn episodes(mut self, episodes: &str) -> Self {
-        self.episodes = Some(episodes.to_owned());
+    /// Filter by episode count. Format is 0-∞. See [`Self::episodes_range()`] for a typed version.
+    pub fn episodes(mut self, episodes: &str) -> Result<Self, BuilderError> {
+        self.search.episodes = Some(check_range(episodes)?);
+        Ok(self)
+    }
+
+    /// Filter by episode count. No max means no upper limit.
+    pub fn episodes_range(mut self, min: u64, max: Option<u64>) -> Self {
+        self.search.episodes = Some(format_range(min, max));
         self
     }
 
     /// Filter by stream. Requires the stream's name. For multiple streams add another streams query for each stream.
     pub fn streams<I: IntoIterator<Item = StreamsQuery>>(mut self, streams: I) -> Self {
-        self.streams = Some(streams.into_iter().collect());
+        self.search.streams = Some(unique(streams));
         self
     }
 
     /// Exclude a stream from the search. Requires the stream's name. For multiple streams add another streams-exclude
     /// query for each stream.
     pub fn streams_exclude<I: IntoIterator<Item = StreamsQuery>>(mut self, streams: I) -> Self {
-        self.streams_exclude = Some(streams.into_iter().collect());
+        self.search.streams_exclude = Some(unique(streams));
         self
     }
 
     /// Filter by MyAnimeList ID. For multiple ids add another mal-ids query for each id.
     pub fn mal_ids<I: IntoIterator<Item = u64>>(mut self, mal_ids: I) -> Self {
-        self.mal_ids = Some(mal_ids.into_iter().collect());
+        self.search.mal_ids = Some(unique(mal_ids));
         self
     }
 
     /// Filter by AniList ID. For multiple ids add another anilist-ids query for each id.
     pub fn anilist_ids<I: IntoIterator<Item = u64>>(mut self, anilist_ids: I) -> Self {
-        self.anilist_ids = Some(anilist_ids.into_iter().collect());
+        self.search.anilist_ids = Some(unique(anilist_ids));
         self
     }
 
     /// Filter by AniDB ID. For multiple ids add another anidb-ids query for each id.
     pub fn anidb_ids<I: IntoIterator<Item = u64>>(mut self, anidb_ids: I) -> Self {
-        self.anidb_ids = Some(anidb_ids.into_iter().collect());
+        self.search.anidb_ids = Some(unique(anidb_ids));
+        self
+    }
+
+    /// The search filters set so far
+    pub fn as_search(&self) -> &AnimeSearch {
+        &self.search
+    }
+
+    /// Whether any filter or text is set. The page, match type, and sorting type don't count.
+    pub fn has_filters(&self) -> bool {
+        self.search.has_filters()
+    }
+
+    /// Make sending a search without filters an error, to guard against accidental broad searches.
+    /// Off by default, in which case an unfiltered search returns the most popular anime.
+    pub fn require_filters(mut self, require: bool) -> Self {
+        self.require_filters = require;
         self
     }
 
+    /// Check for filters which can't match anything, and for missing filters if they're required.
+    /// This is also done when sending.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.require_filters && !self.has_filters() {
+            return Err(ApiError::Validation("the search has no filters".to_owned()));
+        }
+
+        self.search.validate()
+    }
+
+    /// Fetch the page. The api has no field selection parameter, so every anime is returned in full.
     pub async fn send(mut self) -> Result<(RateLimit, AnimePage), ApiError> {
-        let query = serde_qs::to_string(&self).unwrap();
+        self.validate()?;
 
-        let url = format!("{API_ANIME}?{query}");
+        let url = format!("{API_ANIME}?{}", self.search.to_query_string());
 
         self.client.http.get(url, false).await
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, AnimePage), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+
+    /// Fetch the page without deserializing it, to parse it into borrowed types like
+    /// [AnimePageRef](crate::objects::AnimePageRef) which allocate less.
+    #[cfg(feature = "borrowed")]
+    pub async fn send_raw(mut self) -> Result<(RateLimit, RawJson), ApiError> {
+        self.validate()?;
+
+        let url = format!("{API_ANIME}?{}", self.search.to_query_string());
+
+        let (limit, text) = self.client.http.get_text(url, false).await?;
+
+        Ok((limit, RawJson(text)))
+    }
+
+    /// Fetch the page without deserializing it, to parse it into borrowed types like
+    /// [AnimePageRef](crate::objects::AnimePageRef) which allocate less.
+    #[cfg(feature = "borrowed")]
+    pub fn send_raw_blocking(self) -> Result<(RateLimit, RawJson), ApiError> {
+        self.client.runtime().block_on(self.send_raw())
+    }
+
+    /// Fetch the page, keeping the search so other pages can be fetched from it.
+    pub async fn send_paged(self) -> Result<(RateLimit, PagedAnime), ApiError> {
+        let page_size = self.client.anime_page_size;
+        self.send_paged_sized(page_size).await
+    }
+
+    /// send_paged, assuming the page size unless the page shows it
+    async fn send_paged_sized(self, page_size: u64) -> Result<(RateLimit, PagedAnime), ApiError> {
+        let client = self.client.clone();
+        let search = self.search.clone();
+
+        let (limit, page) = self.send().await?;
+
+        let paged = PagedAnime {
+            client,
+            search,
+            page_size: page.page_size(page_size),
+            page,
+        };
+
+        Ok((limit, paged))
+    }
+
+    /// Fetch the page, keeping the search so other pages can be fetched from it.
+    pub fn send_paged_blocking(self) -> Result<(RateLimit, PagedAnime), ApiError> {
+        self.client.runtime().block_on(self.send_paged())
+    }
+
+    /// Fetch every matching anime. Starts at the set page, or the first one.
+    ///
+    /// Pages hold up to [ANIME_PER_PAGE](crate::objects::ANIME_PER_PAGE) anime, so large result sets take many requests.
+    /// A few pages are fetched at once, and the first error is returned. The rate limit is the one of the last page.
+    pub async fn fetch_all(self) -> Result<(RateLimit, Vec<Anime>), ApiError> {
+        let first = self.search.page.unwrap_or(1);
+        let client = self.client.clone();
+        let search = self.search.clone();
+
+        let (mut limit, mut page) = self.page(first).send().await?;
+
+        // nothing more to fetch, e.g. when starting past the last page
+        if page.anime.is_empty() {
+            return Ok((limit, page.anime));
+        }
+
+        let mut seen = HashSet::new();
+        page.dedup(&mut seen);
+
+        // the starting page can be a partial last one, so its size only counts if it's a full first page
+        let last = page.total_pages_for(page.page_size(client.anime_page_size));
+        let mut anime = page.anime;
+
+        let requests = (first + 1..=last).map(|n| {
+            let request = AnimeGet::new(client.clone(), search.clone());
+
+            request.page(n).send()
+        });
+
+        for result in batch::run(requests, BatchMode::FailFast).await {
+            let (page_limit, mut page) = result?;
+
+            limit = page_limit;
+            page.dedup(&mut seen);
+            anime.extend(page.anime);
+        }
+
+        Ok((limit, anime))
+    }
+
+    /// Fetch every matching anime. Starts at the set page, or the first one.
+    ///
+    /// Pages hold up to [ANIME_PER_PAGE](crate::objects::ANIME_PER_PAGE) anime, so large result sets take many requests.
+    /// A few pages are fetched at once, and the first error is returned. The rate limit is the one of the last page.
+    pub fn fetch_all_blocking(self) -> Result<(RateLimit, Vec<Anime>), ApiError> {
+        self.client.runtime().block_on(self.fetch_all())
+    }
+
+    /// Stream every matching anime, fetching the pages as needed. Starts at the set page, or the first one.
+    ///
+    /// Stops once `total_amount` anime were received or a page is empty, and after the first error.
+    /// Anime which show up on two pages (if the results shift between requests) are only returned once.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Anime, ApiError>> {
+        let state = PagesState {
+            page: self.search.page.unwrap_or(1),
+            client: self.client,
+            search: self.search,
+            buffer: VecDeque::new(),
+            seen: HashSet::new(),
+            received: 0,
+            done: false,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(anime) = state.buffer.pop_front() {
+                    return Some((Ok(anime), state));
+                }
+
+                if state.done {
+                    return None;
+                }
+
+                let request = AnimeGet::new(state.client.clone(), state.search.clone());
+
+                match request.page(state.page).send().await {
+                    Ok((_, mut page)) => {
+                        state.received += page.anime.len() as u64;
+                        state.done = page.anime.is_empty() || state.received >= page.total_amount;
+                        state.page += 1;
+
+                        page.dedup(&mut state.seen);
+                        state.buffer.extend(page.anime);
+                    }
+
+                    Err(e) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
     }
 }
 
+/// A page of anime search results, which can fetch other pages of the same search.
+///
+/// Derefs to the [AnimePage].
+pub struct PagedAnime {
+    client: AnimeScheduleClient,
+    search: AnimeSearch,
+    page: AnimePage,
+    page_size: u64,
+}
+
+impl PagedAnime {
+    /// the last page. even a search without results has a first page
+    fn last(&self) -> u64 {
+        self.total_pages().max(1)
+    }
+
+    /// The page size. Taken from a full first page once one was fetched,
+    /// otherwise it's [`AnimeScheduleBuilder::anime_page_size()`](crate::AnimeScheduleBuilder::anime_page_size).
+    pub fn page_size(&self) -> u64 {
+        self.page_size
+    }
+
+    /// How many pages the search has, at [`Self::page_size()`] anime per page.
+    pub fn total_pages(&self) -> u64 {
+        self.page.total_pages_for(self.page_size)
+    }
+
+    /// Whether there are pages after this one
+    pub fn has_next_page(&self) -> bool {
+        self.page.page < self.total_pages()
+    }
+
+    /// Fetch another page of the search. Out of range page numbers are clamped to the first or last page.
+    pub async fn goto_page(&self, page: u64) -> Result<(RateLimit, PagedAnime), ApiError> {
+        let request = AnimeGet::new(self.client.clone(), self.search.clone());
+
+        request
+            .page(page.clamp(1, self.last()))
+            .send_paged_sized(self.page_size)
+            .await
+    }
+
+    /// Fetch another page of the search. Out of range page numbers are clamped to the first or last page.
+    pub fn goto_page_blocking(&self, page: u64) -> Result<(RateLimit, PagedAnime), ApiError> {
+        self.client.runtime().block_on(self.goto_page(page))
+    }
+
+    /// Fetch the next page. None if this is the last page.
+    pub async fn next_page(&self) -> Result<Option<(RateLimit, PagedAnime)>, ApiError> {
+        if !self.has_next_page() {
+            return Ok(None);
+        }
+
+        self.goto_page(self.page.page + 1).await.map(Some)
+    }
+
+    /// Fetch the next page. None if this is the last page.
+    pub fn next_page_blocking(&self) -> Result<Option<(RateLimit, PagedAnime)>, ApiError> {
+        self.client.runtime().block_on(self.next_page())
+    }
+
+    /// Fetch the previous page. None if this is the first page.
+    pub async fn prev_page(&self) -> Result<Option<(RateLimit, PagedAnime)>, ApiError> {
+        if self.page.page <= 1 {
+            return Ok(None);
+        }
+
+        self.goto_page(self.page.page - 1).await.map(Some)
+    }
+
+    /// Fetch the previous page. None if this is the first page.
+    pub fn prev_page_blocking(&self) -> Result<Option<(RateLimit, PagedAnime)>, ApiError> {
+        self.client.runtime().block_on(self.prev_page())
+    }
+
+    /// Fetch the first page
+    pub async fn first_page(&self) -> Result<(RateLimit, PagedAnime), ApiError> {
+        self.goto_page(1).await
+    }
+
+    /// Fetch the first page
+    pub fn first_page_blocking(&self) -> Result<(RateLimit, PagedAnime), ApiError> {
+        self.client.runtime().block_on(self.first_page())
+    }
+
+    /// Fetch the last page
+    pub async fn last_page(&self) -> Result<(RateLimit, PagedAnime), ApiError> {
+        self.goto_page(self.last()).await
+    }
+
+    /// Fetch the last page
+    pub fn last_page_blocking(&self) -> Result<(RateLimit, PagedAnime), ApiError> {
+        self.client.runtime().block_on(self.last_page())
+    }
+
+    pub fn into_page(self) -> AnimePage {
+        self.page
+    }
+}
+
+impl Deref for PagedAnime {
+    type Target = AnimePage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.page
+    }
+}
+
+struct PagesState {
+    client: AnimeScheduleClient,
+    search: AnimeSearch,
+    /// the next page to fetch
+    page: u64,
+    /// anime of the last fetched page which haven't been returned yet
+    buffer: VecDeque<Anime>,
+    /// ids of the anime already returned
+    seen: HashSet<String>,
+    /// how many anime the pages had so far, duplicates included
+    received: u64,
+    done: bool,
+}
+
 /// Fetch the data of a specific anime
+///
+/// The api has no field selection parameter, so the anime is always returned in full.
 pub struct AnimeSlug {
     client: AnimeScheduleClient,
     slug: String,
@@ -338,7 +886,149 @@ impl AnimeSlug {
         self.client.http.get(url, false).await
     }
 
+    /// Like [`Self::send()`], also returning the response's metadata
+    pub async fn send_with_meta(mut self) -> Result<(RateLimit, ResponseMeta, Anime), ApiError> {
+        let url = API_ANIME_SLUG.replace("{slug}", &self.slug);
+
+        self.client.http.get_with_meta(url, false).await
+    }
+
+    /// Like [`Self::send()`], also returning the response's metadata
+    pub fn send_with_meta_blocking(self) -> Result<(RateLimit, ResponseMeta, Anime), ApiError> {
+        self.client.runtime().block_on(self.send_with_meta())
+    }
+
     pub fn send_blocking(self) -> Result<(RateLimit, Anime), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+
+    /// Fetch the anime, returning None if it doesn't exist
+    pub async fn send_optional(mut self) -> Result<(RateLimit, Option<Anime>), ApiError> {
+        let url = API_ANIME_SLUG.replace("{slug}", &self.slug);
+
+        match self.client.http.get(url, false).await {
+            Ok((limit, anime)) => Ok((limit, Some(anime))),
+
+            Err(ApiError::ApiError { status, .. }) if status == StatusCode::NOT_FOUND => {
+                let limit = self
+                    .client
+                    .http
+                    .take_meta()
+                    .and_then(|meta| meta.rate_limit);
+
+                Ok((limit.unwrap_or_else(RateLimit::unknown), None))
+            }
+
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fetch the anime, returning None if it doesn't exist
+    pub fn send_optional_blocking(self) -> Result<(RateLimit, Option<Anime>), ApiError> {
+        self.client.runtime().block_on(self.send_optional())
+    }
+}
+
+/// Fetch the data of multiple specific anime
+pub struct AnimeSlugs {
+    client: AnimeScheduleClient,
+    slugs: Vec<String>,
+    mode: BatchMode,
+}
+
+impl AnimeSlugs {
+    /// Results are in the same order as the slugs. See [BatchMode] for how errors are handled.
+    pub async fn send(self) -> Vec<Result<(RateLimit, Anime), ApiError>> {
+        let client = self.client;
+
+        let requests = self.slugs.into_iter().map(|slug| {
+            let request = AnimeSlug {
+                client: client.clone(),
+                slug,
+            };
+
+            request.send()
+        });
+
+        batch::run(requests, self.mode).await
+    }
+
+    pub fn send_blocking(self) -> Vec<Result<(RateLimit, Anime), ApiError>> {
+        self.client.runtime().block_on(self.send())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn q_truncates_multibyte_chars() {
+        let client = AnimeScheduleClient::test();
+
+        let get = client.anime().get().q(&"進".repeat(201));
+        assert_eq!(get.search.q, Some("進".repeat(200)));
+
+        // the emoji is the 200th character, so it's kept whole
+        let get = client.anime().get().q(&format!("{}😀b", "a".repeat(199)));
+        assert_eq!(get.search.q, Some(format!("{}😀", "a".repeat(199))));
+
+        // the emoji is the 201st character, so it's dropped whole
+        let get = client.anime().get().q(&format!("{}😀", "a".repeat(200)));
+        assert_eq!(get.search.q, Some("a".repeat(200)));
+    }
+
+    #[test]
+    fn search_query_round_trip() {
+        let query = "?q=frieren&mt=any&st=releaseDate&genres=action&genres=drama&genres=action\
+            &years=2023&years=2024&seasons=fall&airing-statuses=ongoing&streams=crunchyroll\
+            &episodes=12-%E2%88%9E&duration=20-30";
+
+        let search = AnimeSearch::from_query_str(query).unwrap();
+
+        assert_eq!(search.q.as_deref(), Some("frieren"));
+        assert_eq!(search.mt, Some(MatchType::Any));
+        assert_eq!(search.st, Some(SortingType::ReleaseDate));
+        // the duplicate is dropped
+        assert_eq!(
+            search.genres,
+            Some(vec!["action".to_owned(), "drama".to_owned()])
+        );
+        assert_eq!(search.years, Some(vec![2023, 2024]));
+        assert_eq!(search.seasons, Some(vec![SeasonQuery::Fall]));
+        assert_eq!(search.airing_statuses, Some(vec![AirStatusQuery::Ongoing]));
+        assert_eq!(search.streams, Some(vec![StreamsQuery::Crunchyroll]));
+        assert_eq!(search.episodes.as_deref(), Some("12-∞"));
+        assert_eq!(search.duration.as_deref(), Some("20-30"));
+
+        let query = search.to_query_string();
+        let reparsed = AnimeSearch::from_query_str(&query).unwrap();
+
+        assert_eq!(reparsed.to_query_string(), query);
+        assert_eq!(reparsed.genres, search.genres);
+        assert_eq!(reparsed.years, search.years);
+        assert_eq!(reparsed.episodes.as_deref(), Some("12-∞"));
+    }
+
+    #[test]
+    fn search_query_accepts_unencoded_infinity() {
+        let search = AnimeSearch::from_query_str("episodes=12-∞").unwrap();
+        assert_eq!(search.episodes.as_deref(), Some("12-∞"));
+    }
+
+    #[test]
+    fn search_query_rejects_invalid_params() {
+        assert!(matches!(
+            AnimeSearch::from_query_str("genres=action&unknown=1"),
+            Err(ApiError::Query(_))
+        ));
+        assert!(matches!(
+            AnimeSearch::from_query_str("seasons=autumn"),
+            Err(ApiError::Query(_))
+        ));
+        assert!(matches!(
+            AnimeSearch::from_query_str("episodes=12-3"),
+            Err(ApiError::Query(_))
+        ));
     }
 }
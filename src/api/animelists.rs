@@ -1,17 +1,19 @@
-use std::{
-    ops::Deref,
-    sync::{Arc, Mutex},
-};
+use std::{ops::Deref, path::Path};
 
 use chrono::prelude::*;
 use const_format::formatcp;
+use http::StatusCode;
 use reqwest::multipart;
+use tracing::debug;
 
 use crate::{
+    auth::SCOPE_ANIMELIST,
     errors::ApiError,
     objects::{Action, AutoScores, ListAnime, ListAnimePut, ListStatus, UserListAnime},
     rate_limit::RateLimit,
-    AnimeScheduleClient, API_URL, RUNTIME,
+    response::ResponseMeta,
+    utils::truncate_chars,
+    AnimeScheduleClient, API_URL,
 };
 
 const API_ANIMELISTS_USERID_ROUTE: &str = formatcp!("{API_URL}/animelists/{{userId}}/{{route}}");
@@ -19,6 +21,31 @@ const API_ANIMELISTS_ROUTE: &str = formatcp!("{API_URL}/animelists/oauth/{{route
 const API_ANIMELISTS_USERID: &str = formatcp!("{API_URL}/animelists/{{userId}}");
 const API_ANIMELISTS: &str = formatcp!("{API_URL}/animelists/oauth");
 
+/// The MyAnimeList import url.
+///
+/// The site's own importer uses a different url than this, but this is the officially documented one.
+/// If it turns out to be wrong, it can be replaced per request with [`AnimeListsPut::url()`].
+fn import_url(user_id: Option<&str>) -> String {
+    match user_id {
+        Some(user_id) => API_ANIMELISTS_USERID.replace("{userId}", user_id),
+        None => API_ANIMELISTS.to_owned(),
+    }
+}
+
+/// The largest MyAnimeList import file the api accepts
+const MAX_XML_SIZE: u64 = 12 * 1024 * 1024;
+
+fn check_xml_size(size: u64) -> Result<(), ApiError> {
+    if size > MAX_XML_SIZE {
+        return Err(ApiError::XmlTooLarge {
+            size,
+            max: MAX_XML_SIZE,
+        });
+    }
+
+    Ok(())
+}
+
 pub struct AnimeListsApi {
     client: AnimeScheduleClient,
 }
@@ -36,6 +63,16 @@ impl AnimeListsApi {
         }
     }
 
+    /// Read another user's public anime list (e.g. a friend's list).
+    ///
+    /// This only uses the app token, so it works without the user's oauth2 tokens.
+    pub fn public(&self, user_id: &str) -> AnimeListsGet {
+        AnimeListsGet {
+            client: self.client.clone(),
+            user_id: Some(user_id.to_owned()),
+        }
+    }
+
     /// Import an anime list from MyAnimeList via .xml file
     pub fn put(&self) -> AnimeListsPut {
         AnimeListsPut {
@@ -43,6 +80,7 @@ impl AnimeListsApi {
             user_id: None,
             overwrite_mal_list: false,
             xml: None,
+            url: None,
         }
     }
 
@@ -65,7 +103,7 @@ pub struct AnimeListsGet {
 }
 
 impl AnimeListsGet {
-    /// set the user id to get the lists from
+    /// set the user id to get the lists from. Other users' lists are fetched with only the app token
     pub fn user_id(mut self, user_id: &str) -> Self {
         self.user_id = Some(user_id.to_owned());
         self
@@ -93,12 +131,34 @@ impl AnimeListsGet {
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, UserListAnime), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
     }
 }
 
+/// The raw ETag header value, including any `W/` prefix and quotes.
+///
+/// Pass it to [`AnimeListsPutRoute::etag()`] as is. It's sent back exactly as received,
+/// since that's what the server compares against.
 #[derive(Debug)]
 pub struct ETag(pub String);
+
+impl ETag {
+    /// Whether this is a weak etag, i.e. prefixed with `W/`
+    pub fn is_weak(&self) -> bool {
+        self.0.trim_start().starts_with("W/")
+    }
+
+    /// The opaque tag, without the weak prefix and quotes
+    pub fn value(&self) -> &str {
+        let tag = self.0.trim();
+        let tag = tag.strip_prefix("W/").unwrap_or(tag);
+
+        tag.strip_prefix('"')
+            .and_then(|tag| tag.strip_suffix('"'))
+            .unwrap_or(tag)
+    }
+}
+
 impl Deref for ETag {
     type Target = str;
 
@@ -124,7 +184,20 @@ impl AnimeListsGetRoute {
         self
     }
 
-    pub async fn send(mut self) -> Result<(RateLimit, ETag, ListAnime), ApiError> {
+    pub async fn send(self) -> Result<(RateLimit, ETag, ListAnime), ApiError> {
+        let (limit, meta, listanime) = self.send_with_meta().await?;
+
+        Ok((limit, ETag(meta.etag.unwrap_or_default()), listanime))
+    }
+
+    pub fn send_blocking(self) -> Result<(RateLimit, ETag, ListAnime), ApiError> {
+        self.client.runtime().block_on(self.send())
+    }
+
+    /// Like [`Self::send()`], returning all of the response's metadata rather than just the ETag
+    pub async fn send_with_meta(
+        mut self,
+    ) -> Result<(RateLimit, ResponseMeta, ListAnime), ApiError> {
         let is_self = self.user_id.is_none();
 
         let url = if let Some(user_id) = self.user_id {
@@ -135,29 +208,12 @@ impl AnimeListsGetRoute {
             API_ANIMELISTS_ROUTE.replace("{route}", &self.route)
         };
 
-        let etag = Arc::new(Mutex::new(None));
-
-        let etag_clone = etag.clone();
-        self.client.http.response_cb(move |headers| {
-            let mut lock = etag_clone.lock().unwrap();
-
-            *lock = Some(ETag(
-                headers
-                    .get("etag")
-                    .and_then(|h| h.to_str().ok())
-                    .unwrap_or_default()
-                    .to_owned(),
-            ));
-        });
-
-        let (limit, listanime) = self.client.http.get(url, is_self).await?;
-
-        let mut lock = etag.lock().unwrap();
-        Ok((limit, lock.take().unwrap(), listanime))
+        self.client.http.get_with_meta(url, is_self).await
     }
 
-    pub fn send_blocking(self) -> Result<(RateLimit, ETag, ListAnime), ApiError> {
-        RUNTIME.block_on(self.send())
+    /// Like [`Self::send()`], returning all of the response's metadata rather than just the ETag
+    pub fn send_with_meta_blocking(self) -> Result<(RateLimit, ResponseMeta, ListAnime), ApiError> {
+        self.client.runtime().block_on(self.send_with_meta())
     }
 }
 
@@ -171,6 +227,8 @@ pub struct AnimeListsPut {
     overwrite_mal_list: bool,
     /// the myanimelist xml import file in the request. Up to 12mb in file size
     xml: Option<String>,
+    /// replaces the import url
+    url: Option<String>,
 }
 
 impl AnimeListsPut {
@@ -181,6 +239,7 @@ impl AnimeListsPut {
             route: route.to_owned(),
             etag: None,
             list: ListAnimePut::default(),
+            invalid_date: false,
         }
     }
 
@@ -204,22 +263,49 @@ impl AnimeListsPut {
         self
     }
 
+    /// Send the import to this url instead of the documented one, e.g. if the api moves it.
+    /// The user id is ignored when this is set.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    /// Read a MyAnimeList .xml export file to import. Up to 12MB in file size.
+    pub async fn xml_file(mut self, path: impl AsRef<Path>) -> Result<Self, ApiError> {
+        let path = path.as_ref();
+
+        // check first, so a huge file isn't read for nothing
+        let size = tokio::fs::metadata(path).await?.len();
+        check_xml_size(size)?;
+
+        self.xml = Some(tokio::fs::read_to_string(path).await?);
+        Ok(self)
+    }
+
+    /// Read a MyAnimeList .xml export file to import. Up to 12MB in file size.
+    pub fn xml_file_blocking(self, path: impl AsRef<Path>) -> Result<Self, ApiError> {
+        self.client.runtime().block_on(self.xml_file(path))
+    }
+
     pub async fn send(mut self) -> Result<RateLimit, ApiError> {
-        let url = if let Some(user_id) = self.user_id {
-            API_ANIMELISTS_USERID.replace("{userId}", &user_id)
-        } else {
-            API_ANIMELISTS.to_owned()
+        self.client.auth.require_scope(SCOPE_ANIMELIST)?;
+
+        let url = match self.url {
+            Some(url) => url,
+            None => import_url(self.user_id.as_deref()),
         };
 
         let Some(xml) = self.xml else {
             return Err(ApiError::Xml);
         };
 
+        check_xml_size(xml.len() as u64)?;
+
+        debug!(bytes = xml.len(), "uploading mal list");
+
         self.client.http.request_cb(move |request| {
             // The docs do not say how to do this part
             // so this was reverse engineered from the site's xml importer
-            // the site uses a different api url for this, but I'm still using
-            // the officially listed api url
             //
             // reverse engineer from here:
             // https://animeschedule.net/users/<your_username>/settings/import-export
@@ -237,13 +323,28 @@ impl AnimeListsPut {
             request.multipart(form)
         });
 
-        let (limit, _) = self.client.http.put::<()>(url, true).await?;
+        // for a rejected import file, the server's error body describes what was wrong with it.
+        // other failures (e.g. an expired token or an outage) are kept as they are
+        let (limit, _) = self
+            .client
+            .http
+            .put::<()>(url, true)
+            .await
+            .map_err(|e| match e {
+                ApiError::ApiError { status, error }
+                    if status == StatusCode::BAD_REQUEST
+                        || status == StatusCode::UNPROCESSABLE_ENTITY =>
+                {
+                    ApiError::ImportFailed(error)
+                }
+                e => e,
+            })?;
 
         Ok(limit)
     }
 
     pub fn send_blocking(self) -> Result<RateLimit, ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
     }
 }
 
@@ -259,6 +360,8 @@ pub struct AnimeListsPutRoute {
     route: String,
     /// the put list
     list: ListAnimePut,
+    /// whether a date setter received an invalid date
+    invalid_date: bool,
 }
 
 impl AnimeListsPutRoute {
@@ -271,6 +374,9 @@ impl AnimeListsPutRoute {
     /// An Etag header in the request headers. Mandatory and must be valid. You can get a
     /// valid Etag by doing a GET request on a specific List Anime beforehand and getting it
     /// from the response headers.
+    ///
+    /// This is sent in an `ETag` request header rather than the standard `If-Match`, since that's what the api
+    /// documents for this endpoint.
     pub fn etag(mut self, etag: &str) -> Self {
         self.etag = Some(etag.to_owned());
         self
@@ -320,12 +426,33 @@ impl AnimeListsPutRoute {
         self
     }
 
+    /// The date the anime was started watching, at midnight UTC.
+    ///
+    /// An invalid date makes [`Self::send()`] fail.
+    pub fn start_date_ymd(mut self, year: i32, month: u32, day: u32) -> Self {
+        match utc_midnight(year, month, day) {
+            Some(datetime) => self.list.start_date = Some(datetime),
+            None => self.invalid_date = true,
+        }
+
+        self
+    }
+
+    /// The date the anime was finished watching, at midnight UTC.
+    ///
+    /// An invalid date makes [`Self::send()`] fail.
+    pub fn end_date_ymd(mut self, year: i32, month: u32, day: u32) -> Self {
+        match utc_midnight(year, month, day) {
+            Some(datetime) => self.list.end_date = Some(datetime),
+            None => self.invalid_date = true,
+        }
+
+        self
+    }
+
     /// User note. Max length is 1000.
     pub fn note(mut self, note: &str) -> Self {
-        let mut note = note.to_owned();
-        note.truncate(1000);
-
-        self.list.note = Some(note);
+        self.list.note = Some(truncate_chars(note, 1000));
         self
     }
 
@@ -335,11 +462,31 @@ impl AnimeListsPutRoute {
         self
     }
 
-    pub async fn send(mut self) -> Result<RateLimit, ApiError> {
+    /// Delete the user's note. Same as the [Action::DeleteNote] action.
+    pub fn delete_note(self) -> Self {
+        self.action(Action::DeleteNote)
+    }
+
+    pub async fn send(self) -> Result<RateLimit, ApiError> {
         if self.etag.is_none() {
             return Err(ApiError::Etag);
         }
 
+        self.put().await
+    }
+
+    pub fn send_blocking(self) -> Result<RateLimit, ApiError> {
+        self.client.runtime().block_on(self.send())
+    }
+
+    /// Send the request, with the ETag header only if one is set
+    async fn put(mut self) -> Result<RateLimit, ApiError> {
+        if self.invalid_date {
+            return Err(ApiError::Date);
+        }
+
+        self.client.auth.require_scope(SCOPE_ANIMELIST)?;
+
         let url = if let Some(user_id) = self.user_id {
             API_ANIMELISTS_USERID_ROUTE
                 .replace("{userId}", &user_id)
@@ -349,9 +496,13 @@ impl AnimeListsPutRoute {
         };
 
         self.client.http.request_cb(move |request| {
-            request
-                .json(&self.list)
-                .header("ETag", self.etag.as_ref().unwrap())
+            let request = request.json(&self.list);
+
+            // the api documents a literal ETag request header for this, not If-Match
+            match &self.etag {
+                Some(etag) => request.header("ETag", etag),
+                None => request,
+            }
         });
 
         let (limit, _) = self.client.http.put::<()>(url, true).await?;
@@ -359,8 +510,30 @@ impl AnimeListsPutRoute {
         Ok(limit)
     }
 
-    pub fn send_blocking(self) -> Result<RateLimit, ApiError> {
-        RUNTIME.block_on(self.send())
+    /// Fetch the route's current ETag, then send. Any ETag set with [`Self::etag()`] is replaced.
+    ///
+    /// If the anime isn't on the list yet there is no ETag to fetch, so the request is sent without one.
+    pub async fn send_fetching_etag(mut self) -> Result<RateLimit, ApiError> {
+        let get = AnimeListsGetRoute {
+            client: self.client.clone(),
+            user_id: self.user_id.clone(),
+            route: self.route.clone(),
+        };
+
+        self.etag = match get.send_with_meta().await {
+            Ok((_, meta, _)) => meta.etag.filter(|etag| !etag.is_empty()),
+            Err(ApiError::ApiError { status, .. }) if status == StatusCode::NOT_FOUND => None,
+            Err(e) => return Err(e),
+        };
+
+        self.put().await
+    }
+
+    /// Fetch the route's current ETag, then send. Any ETag set with [`Self::etag()`] is replaced.
+    ///
+    /// If the anime isn't on the list yet there is no ETag to fetch, so the request is sent without one.
+    pub fn send_fetching_etag_blocking(self) -> Result<RateLimit, ApiError> {
+        self.client.runtime().block_on(self.send_fetching_etag())
     }
 }
 
@@ -392,6 +565,8 @@ impl AnimeListsDelete {
             return Err(ApiError::Route);
         };
 
+        self.client.auth.require_scope(SCOPE_ANIMELIST)?;
+
         let url = if let Some(user_id) = self.user_id {
             API_ANIMELISTS_USERID_ROUTE
                 .replace("{userId}", &user_id)
@@ -406,6 +581,57 @@ impl AnimeListsDelete {
     }
 
     pub fn send_blocking(self) -> Result<RateLimit, ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+}
+
+/// midnight in utc of a calendar date
+fn utc_midnight(year: i32, month: u32, day: u32) -> Option<DateTime<FixedOffset>> {
+    let datetime = NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(0, 0, 0)?;
+    Some(datetime.and_utc().fixed_offset())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{response, serve_once};
+
+    #[test]
+    fn note_truncates_multibyte_chars() {
+        let client = AnimeScheduleClient::test();
+
+        let put = client
+            .animelists()
+            .put()
+            .route("frieren")
+            .note(&"進".repeat(1001));
+        assert_eq!(put.list.note, Some("進".repeat(1000)));
+
+        let put = client
+            .animelists()
+            .put()
+            .route("frieren")
+            .note(&format!("{}😀b", "a".repeat(999)));
+        assert_eq!(put.list.note, Some(format!("{}😀", "a".repeat(999))));
+    }
+
+    #[tokio::test]
+    async fn etag_is_sent_in_etag_header() {
+        let (url, server) = serve_once(response("204 No Content", &[], "")).await;
+        let client = AnimeScheduleClient::test_with_base_url(url);
+
+        client
+            .animelists()
+            .put()
+            .route("frieren")
+            .etag("\"abc\"")
+            .send()
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.head.starts_with("PUT /animelists/oauth/frieren "));
+        assert_eq!(request.header("etag"), Some("\"abc\""));
+        assert_eq!(request.header("if-match"), None);
     }
 }
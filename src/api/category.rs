@@ -6,7 +6,8 @@ use crate::{
     errors::ApiError,
     objects::{Categories, Category},
     rate_limit::RateLimit,
-    AnimeScheduleClient, API_URL, RUNTIME,
+    utils::truncate_chars,
+    AnimeScheduleClient, API_URL,
 };
 
 const API_CATEGORITES_TYPE: &str = formatcp!("{API_URL}/categories/{{categoryType}}");
@@ -59,10 +60,7 @@ impl CategoryGet {
 
     /// Filter by text. Maximum length is 200.
     pub fn q(mut self, q: &str) -> Self {
-        let mut q = q.to_owned();
-        q.truncate(200);
-
-        self.q = Some(q);
+        self.q = Some(truncate_chars(q, 200));
         self
     }
 
@@ -78,7 +76,7 @@ impl CategoryGet {
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, Categories), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
     }
 }
 
@@ -100,6 +98,25 @@ impl CategorySlug {
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, Category), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn q_truncates_multibyte_chars() {
+        let client = AnimeScheduleClient::test();
+
+        let get = client.categories("genres").get().q(&"進".repeat(201));
+        assert_eq!(get.q, Some("進".repeat(200)));
+
+        let get = client
+            .categories("genres")
+            .get()
+            .q(&format!("{}😀b", "a".repeat(199)));
+        assert_eq!(get.q, Some(format!("{}😀", "a".repeat(199))));
     }
 }
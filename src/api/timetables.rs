@@ -1,12 +1,17 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{DateTime, Datelike as _, Days, NaiveDate, TimeZone, Utc};
 use const_format::formatcp;
+use futures_util::{stream, Stream};
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 
 use crate::{
+    batch::{self, BatchMode},
     errors::ApiError,
-    objects::{AirTypeQuery, Timetables},
+    objects::{AirTypeQuery, TimetableAnime, Timetables},
     rate_limit::RateLimit,
-    AnimeScheduleClient, API_URL, RUNTIME,
+    AnimeScheduleClient, API_URL,
 };
 
 const API_TIMETABLES: &str = formatcp!("{API_URL}/timetables");
@@ -30,11 +35,106 @@ impl TimetablesApi {
             tz: None,
         }
     }
+
+    /// Fetch a week's timetable for multiple air types at once. The requests are sent concurrently.
+    pub fn get_multi(&self, air_types: &[AirTypeQuery]) -> TimetablesGetMulti {
+        let mut types = Vec::with_capacity(air_types.len());
+        for air_type in air_types {
+            if !types.contains(air_type) {
+                types.push(*air_type);
+            }
+        }
+
+        TimetablesGetMulti {
+            get: self.get(),
+            air_types: types,
+        }
+    }
+
+    /// Stream the timetable anime of every ISO week from start to end (inclusive).
+    ///
+    /// Anime which appear in multiple weeks are only returned once, by their route and episode number.
+    /// If the rate limit runs out, this waits until it resets before fetching the next week.
+    /// The stream ends after the first error.
+    pub fn range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Stream<Item = Result<TimetableAnime, ApiError>> {
+        let state = RangeState {
+            api: TimetablesApi::new(self.client.clone()),
+            weeks: iso_weeks(start.date_naive(), end.date_naive()),
+            buffer: VecDeque::new(),
+            seen: HashSet::new(),
+            limit: None,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(anime) = state.buffer.pop_front() {
+                    return Some((Ok(anime), state));
+                }
+
+                let (year, week) = state.weeks.pop_front()?;
+
+                if let Some(limit) = state.limit {
+                    limit.wait_if_exhausted().await;
+                }
+
+                match state.api.get().year(year).week(week).send().await {
+                    Ok((limit, timetables)) => {
+                        state.limit = Some(limit);
+
+                        let seen = &mut state.seen;
+                        state
+                            .buffer
+                            .extend(timetables.0.into_iter().filter(|anime| {
+                                seen.insert((anime.route.clone(), anime.episode_number))
+                            }));
+                    }
+
+                    Err(e) => {
+                        state.weeks.clear();
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+    }
+}
+
+struct RangeState {
+    api: TimetablesApi,
+    /// (iso year, iso week) left to fetch
+    weeks: VecDeque<(u16, u16)>,
+    /// anime of the last fetched week which haven't been returned yet
+    buffer: VecDeque<TimetableAnime>,
+    /// (route, episode number) already returned
+    seen: HashSet<(String, u64)>,
+    limit: Option<RateLimit>,
+}
+
+/// every (iso year, iso week) from start to end, inclusive
+fn iso_weeks(start: NaiveDate, end: NaiveDate) -> VecDeque<(u16, u16)> {
+    let mut weeks = VecDeque::new();
+
+    // start on the monday so stepping a week at a time can't skip over end's week
+    let mut date = start.checked_sub_days(Days::new(start.weekday().num_days_from_monday() as u64));
+
+    while let Some(day) = date.filter(|day| *day <= end) {
+        // the iso year, not the calendar year, as late december can be week 1 of the next year
+        let week = day.iso_week();
+        weeks.push_back((week.year() as u16, week.week() as u16));
+
+        date = day.checked_add_days(Days::new(7));
+    }
+
+    weeks
 }
 
 /// Fetches an array of a week's timetable anime. Valid airType values are raw, sub, dub and all. Defaults to all.
 #[skip_serializing_none]
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct TimetablesGet {
     #[serde(skip)]
@@ -76,8 +176,42 @@ impl TimetablesGet {
         self
     }
 
-    /// Fetch the data of multiple categories by query
+    /// Set the week and year to the current week.
+    pub fn current_week(self) -> Self {
+        self.for_date(Utc::now())
+    }
+
+    /// Set the week and year to the week of the date. These are the iso week and year,
+    /// so late december can be week 1 of the next year.
+    pub fn for_date<Tz: TimeZone>(self, date: DateTime<Tz>) -> Self {
+        let week = date.iso_week();
+        self.year(week.year() as u16).week(week.week() as u16)
+    }
+
+    /// Check for parameters the api would reject. This is also done when sending.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.week.is_some() != self.year.is_some() {
+            return Err(ApiError::Validation(
+                "week and year must be set together".to_owned(),
+            ));
+        }
+
+        if let Some(week) = self.week.filter(|week| !(1..=53).contains(week)) {
+            return Err(ApiError::Validation(format!(
+                "week {week} is out of range 1-53"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the week's timetable anime.
+    ///
+    /// The response doesn't include which week the server used. If you need to label the week,
+    /// set [`Self::week()`] and [`Self::year()`] explicitly instead of relying on the default.
     pub async fn send(mut self) -> Result<(RateLimit, Timetables), ApiError> {
+        self.validate()?;
+
         let url = if let Some(air_type) = self.air_type {
             API_TIMETABLES_AIR_TYPE.replace("{airType}", air_type.into())
         } else {
@@ -92,6 +226,65 @@ impl TimetablesGet {
     }
 
     pub fn send_blocking(self) -> Result<(RateLimit, Timetables), ApiError> {
-        RUNTIME.block_on(self.send())
+        self.client.runtime().block_on(self.send())
+    }
+}
+
+/// Fetches a week's timetable anime for multiple air types
+pub struct TimetablesGetMulti {
+    get: TimetablesGet,
+    air_types: Vec<AirTypeQuery>,
+}
+
+impl TimetablesGetMulti {
+    /// The week's number in a year. Requires the year query parameter.
+    pub fn week(mut self, week: u16) -> Self {
+        self.get = self.get.week(week);
+        self
+    }
+
+    /// Set the week and year to the current week.
+    pub fn current_week(mut self) -> Self {
+        self.get = self.get.current_week();
+        self
+    }
+
+    /// Set the week and year to the week of the date. These are the iso week and year,
+    /// so late december can be week 1 of the next year.
+    pub fn for_date<Tz: TimeZone>(mut self, date: DateTime<Tz>) -> Self {
+        self.get = self.get.for_date(date);
+        self
+    }
+
+    /// The year the requested week belongs in. Requires the week query parameter.
+    pub fn year(mut self, year: u16) -> Self {
+        self.get = self.get.year(year);
+        self
+    }
+
+    /// A IATA timezone string. Converts all of the times to that timezones. Defaults to Europe/London (GMT/BST.)
+    /// Warning: It auto-converts for daylights savings if the target timezone has it.
+    pub fn tz(mut self, tz: &str) -> Self {
+        self.get = self.get.tz(tz);
+        self
+    }
+
+    /// Fetch the timetables of every air type. Fails if any of the requests fail.
+    pub async fn send(self) -> Result<HashMap<AirTypeQuery, (RateLimit, Timetables)>, ApiError> {
+        let Self { get, air_types } = self;
+
+        let requests = air_types.into_iter().map(|air_type| {
+            let get = get.clone().air_type(air_type);
+            async move { get.send().await.map(|res| (air_type, res)) }
+        });
+
+        batch::run(requests, BatchMode::FailFast)
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    pub fn send_blocking(self) -> Result<HashMap<AirTypeQuery, (RateLimit, Timetables)>, ApiError> {
+        self.get.client.runtime().block_on(self.send())
     }
 }
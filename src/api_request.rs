@@ -1,11 +1,28 @@
-use std::sync::Arc;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use http::HeaderMap;
-use reqwest::{Client, IntoUrl, RequestBuilder};
-use serde::de::DeserializeOwned;
+use bytes::Bytes;
+use http::{
+    header::{CONTENT_TYPE, LOCATION},
+    StatusCode,
+};
+use reqwest::{Client, IntoUrl, RequestBuilder, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::Semaphore;
 use tracing::debug;
 
-use crate::{errors::ApiError, rate_limit::RateLimit, utils::IsJson, Auth};
+use crate::{
+    errors::ApiError,
+    rate_limit::{retry_after, RateLimit, Throttle},
+    response::ResponseMeta,
+    utils::{summarize_body, IsJson},
+    Auth, API_URL,
+};
+
+/// How many redirects a download follows
+const MAX_DOWNLOAD_REDIRECTS: usize = 10;
 
 #[derive(Copy, Clone, Debug)]
 pub(crate) enum RequestMethod {
@@ -14,47 +31,136 @@ pub(crate) enum RequestMethod {
     Delete,
 }
 
+/// How to handle an exhausted rate limit
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RateLimitPolicy {
+    /// wait for the rate limit to reset, and retry rate limited requests
+    pub auto: bool,
+    /// the longest to wait for a reset. longer waits aren't done, so nothing blocks forever
+    pub max_wait: Duration,
+    /// how many times a rate limited request is retried
+    pub max_retries: u32,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            auto: false,
+            max_wait: Duration::from_secs(60),
+            max_retries: 3,
+        }
+    }
+}
+
 pub(crate) struct ApiRequest {
     // these fields are synced between all clients
     auth: Arc<Auth>,
     http: reqwest::Client,
+    // the last seen rate limit
+    rate_limit: Arc<Mutex<Option<RateLimit>>>,
+    policy: RateLimitPolicy,
+    // paces requests to the rate limit when throttling
+    throttle: Option<Arc<Throttle>>,
+    // bounds how many requests are in flight at once
+    semaphore: Option<Arc<Semaphore>>,
+    // refresh the access token before authenticated requests when it's about to expire
+    auto_refresh: bool,
+    // replaces the api url in requests, e.g. for a mock server
+    base_url: Option<Url>,
     // these are not
+    // the last response's metadata. kept when the request failed too, e.g. for the rate limit of a 404
+    meta: Option<ResponseMeta>,
     #[allow(clippy::complexity)]
-    response_cb: Option<Box<dyn FnOnce(&HeaderMap) + 'static>>,
-    request_cb: Option<Box<dyn FnOnce(RequestBuilder) -> RequestBuilder + 'static>>,
+    request_cb: Option<Box<dyn FnOnce(RequestBuilder) -> RequestBuilder + Send + Sync + 'static>>,
 }
 
 impl Clone for ApiRequest {
     fn clone(&self) -> Self {
-        let ApiRequest { auth, http, .. } = self;
+        let ApiRequest {
+            auth,
+            http,
+            rate_limit,
+            policy,
+            throttle,
+            semaphore,
+            auto_refresh,
+            base_url,
+            ..
+        } = self;
 
         ApiRequest {
             auth: auth.clone(),
             http: http.clone(),
-            // we don't need to clone this. it's set individually per call, and runs only once
-            response_cb: None,
+            rate_limit: rate_limit.clone(),
+            policy: *policy,
+            throttle: throttle.clone(),
+            semaphore: semaphore.clone(),
+            auto_refresh: *auto_refresh,
+            base_url: base_url.clone(),
+            // we don't need to clone these. they're set individually per call
+            meta: None,
             request_cb: None,
         }
     }
 }
 
 impl ApiRequest {
-    pub fn new(auth: Arc<Auth>, http: Client) -> Self {
+    pub fn new(
+        auth: Arc<Auth>,
+        http: Client,
+        policy: RateLimitPolicy,
+        throttle: bool,
+        max_concurrent: Option<usize>,
+        auto_refresh: bool,
+        base_url: Option<Url>,
+    ) -> Self {
         Self {
             auth,
             http,
-            response_cb: None,
+            rate_limit: Arc::default(),
+            policy,
+            throttle: throttle.then(Arc::default),
+            semaphore: max_concurrent.map(|permits| Arc::new(Semaphore::new(permits))),
+            auto_refresh,
+            base_url,
+            meta: None,
             request_cb: None,
         }
     }
 
-    pub fn response_cb(&mut self, response_cb: impl FnOnce(&HeaderMap) + 'static) {
-        self.response_cb = Some(Box::new(response_cb));
+    /// How many more requests may start right now. None if concurrency isn't limited
+    pub fn available_permits(&self) -> Option<usize> {
+        self.semaphore
+            .as_ref()
+            .map(|semaphore| semaphore.available_permits())
+    }
+
+    /// The last seen rate limit
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    /// Set the last seen rate limit, and refill the throttle's bucket from it. Unknown ones are ignored
+    pub fn set_rate_limit(&self, limit: RateLimit) {
+        if limit.unknown {
+            return;
+        }
+
+        *self.rate_limit.lock().unwrap() = Some(limit);
+
+        if let Some(throttle) = &self.throttle {
+            throttle.seed(limit);
+        }
+    }
+
+    /// The metadata of the last response. Also set when the request failed
+    pub fn take_meta(&mut self) -> Option<ResponseMeta> {
+        self.meta.take()
     }
 
     pub fn request_cb(
         &mut self,
-        request_cb: impl FnOnce(RequestBuilder) -> RequestBuilder + 'static,
+        request_cb: impl FnOnce(RequestBuilder) -> RequestBuilder + Send + Sync + 'static,
     ) {
         self.request_cb = Some(Box::new(request_cb));
     }
@@ -64,6 +170,22 @@ impl ApiRequest {
         url: impl IntoUrl,
         is_auth: bool,
     ) -> Result<(RateLimit, D), ApiError>
+    where
+        D: DeserializeOwned,
+    {
+        let (limit, _, data) = self
+            .api_request(url.into_url()?, RequestMethod::Get, is_auth)
+            .await?;
+
+        Ok((limit, data))
+    }
+
+    /// Like [Self::get], also returning the response's metadata
+    pub async fn get_with_meta<D>(
+        &mut self,
+        url: impl IntoUrl,
+        is_auth: bool,
+    ) -> Result<(RateLimit, ResponseMeta, D), ApiError>
     where
         D: DeserializeOwned,
     {
@@ -79,8 +201,11 @@ impl ApiRequest {
     where
         D: DeserializeOwned,
     {
-        self.api_request(url.into_url()?, RequestMethod::Delete, is_auth)
-            .await
+        let (limit, _, data) = self
+            .api_request(url.into_url()?, RequestMethod::Delete, is_auth)
+            .await?;
+
+        Ok((limit, data))
     }
 
     pub async fn put<D>(
@@ -91,8 +216,129 @@ impl ApiRequest {
     where
         D: DeserializeOwned,
     {
-        self.api_request(url.into_url()?, RequestMethod::Put, is_auth)
-            .await
+        let (limit, _, data) = self
+            .api_request(url.into_url()?, RequestMethod::Put, is_auth)
+            .await?;
+
+        Ok((limit, data))
+    }
+
+    /// The raw json body, for deserializing data which borrows from it
+    #[cfg(feature = "borrowed")]
+    pub async fn get_text(
+        &mut self,
+        url: impl IntoUrl,
+        is_auth: bool,
+    ) -> Result<(RateLimit, String), ApiError> {
+        let (limit, _, text) = self
+            .api_request_text(url.into_url()?, RequestMethod::Get, is_auth, true)
+            .await?;
+
+        Ok((limit, text))
+    }
+
+    /// The body of a successful response as is, for endpoints which may not respond with json
+    pub async fn get_raw(
+        &mut self,
+        url: impl IntoUrl,
+        is_auth: bool,
+    ) -> Result<(RateLimit, String), ApiError> {
+        let (limit, _, text) = self
+            .api_request_text(url.into_url()?, RequestMethod::Get, is_auth, false)
+            .await?;
+
+        Ok((limit, text))
+    }
+
+    /// The url with the api url replaced by the base url, if one was set
+    fn rebase(&self, url: Url) -> Result<Url, ApiError> {
+        let Some(base_url) = &self.base_url else {
+            return Ok(url);
+        };
+
+        let Some(path) = url.as_str().strip_prefix(API_URL) else {
+            return Ok(url);
+        };
+
+        let base_url = base_url.as_str().trim_end_matches('/');
+
+        Ok(Url::parse(&format!("{base_url}{path}"))?)
+    }
+
+    /// Fetch a file the api linked to (e.g. an image), returning its content type and bytes.
+    ///
+    /// No credentials are sent, since the file may be on another host. It isn't an api request, so it doesn't count
+    /// against the rate limit.
+    ///
+    /// Redirects are followed even if api requests don't follow them, since files are often served through a cdn.
+    pub async fn download(&self, mut url: Url) -> Result<(Option<String>, Bytes), ApiError> {
+        // the semaphore is never closed, so this can't fail
+        let _permit = match &self.semaphore {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        let mut response = self.http.get(url.clone()).send().await?;
+
+        // only reached if the client doesn't follow redirects itself
+        for _ in 0..MAX_DOWNLOAD_REDIRECTS {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok());
+
+            let Some(location) = location.filter(|_| response.status().is_redirection()) else {
+                break;
+            };
+
+            url = url.join(location)?;
+            response = self.http.get(url.clone()).send().await?;
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        let status = response.status();
+
+        if !status.is_success() {
+            let text = response.text().await?;
+
+            return Err(ApiError::ApiError {
+                status,
+                error: summarize_body(&text, content_type.as_deref()),
+            });
+        }
+
+        let data = response.bytes().await?;
+
+        debug!(
+            status = status.as_u16(),
+            bytes = data.len(),
+            "downloaded file"
+        );
+
+        Ok((content_type, data))
+    }
+
+    /// Take one of the remaining requests when throttling, waiting for the reset if there are none left
+    async fn take_request(&self) {
+        if let Some(throttle) = &self.throttle {
+            throttle.take().await;
+        }
+    }
+
+    /// Sleep for the duration. Returns false without sleeping if that's longer than the max wait.
+    async fn wait(&self, wait: Duration) -> bool {
+        if wait > self.policy.max_wait {
+            return false;
+        }
+
+        tokio::time::sleep(wait).await;
+
+        true
     }
 
     /// is_auth : Use user authentication in request; otherwise use ClientID header
@@ -102,51 +348,297 @@ impl ApiRequest {
         method: RequestMethod,
         // whether to use oauth2 access token or client id header
         is_auth: bool,
-    ) -> Result<(RateLimit, D), ApiError>
+    ) -> Result<(RateLimit, ResponseMeta, D), ApiError>
     where
         D: DeserializeOwned,
     {
+        let (limit, meta, text) = self.api_request_text(url, method, is_auth, true).await?;
+
+        Ok((limit, meta, deserialize(&text)?))
+    }
+
+    /// Sends the request, and returns the body of a successful response
+    async fn api_request_text(
+        &mut self,
+        url: impl IntoUrl,
+        method: RequestMethod,
+        is_auth: bool,
+        // whether a successful response must be json
+        json: bool,
+    ) -> Result<(RateLimit, ResponseMeta, String), ApiError> {
+        let url = self.rebase(url.into_url()?)?;
+
         let request = match method {
-            RequestMethod::Get => self.http.get(url.into_url()?),
-            RequestMethod::Delete => self.http.delete(url.into_url()?),
-            RequestMethod::Put => self.http.put(url.into_url()?),
+            RequestMethod::Get => self.http.get(url),
+            RequestMethod::Delete => self.http.delete(url),
+            RequestMethod::Put => self.http.put(url),
         };
 
+        if is_auth && self.auto_refresh && self.auth.is_refresh_valid() {
+            // refreshed once the token counts as expired
+            let margin = self.auth.expiry_skew();
+            self.auth.refresh_if_expiring(margin).await?;
+        }
+
         let request = if is_auth {
             request.bearer_auth(self.auth.access_token().secret())
         } else {
             request.bearer_auth(self.auth.app_token().secret())
         };
 
-        let request = if let Some(cb) = self.request_cb.take() {
+        let mut request = if let Some(cb) = self.request_cb.take() {
             cb(request)
         } else {
             request
         };
 
-        let response = request.send().await?;
+        let mut retries = 0;
+
+        let (response, limit, _permit) = loop {
+            // throttling already waits for the reset
+            if self.policy.auto && self.throttle.is_none() {
+                let exhausted = self
+                    .rate_limit()
+                    .filter(|limit| limit.is_exhausted() && !limit.time_until_reset().is_zero());
+
+                if let Some(limit) = exhausted {
+                    // if it's too long, send anyway and let the server decide
+                    self.wait(limit.time_until_reset()).await;
+                }
+            }
+
+            self.take_request().await;
+
+            // held until the body was read. the semaphore is never closed, so this can't fail
+            let permit = match &self.semaphore {
+                Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+                None => None,
+            };
+
+            // requests with streamed bodies can't be cloned, so those are never retried
+            let retry = if self.policy.auto && retries < self.policy.max_retries {
+                request.try_clone()
+            } else {
+                None
+            };
+
+            let response = request.send().await?;
+
+            let limit = RateLimit::new(response.headers());
+
+            if let Some(limit) = limit {
+                self.set_rate_limit(limit);
+            }
+
+            match retry {
+                Some(next) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let wait = retry_after(response.headers())
+                        .or_else(|| limit.map(|limit| limit.time_until_reset()))
+                        .unwrap_or_default()
+                        // the reset is in whole seconds, so wait at least one to not retry within the same window
+                        .max(Duration::from_secs(1));
+
+                    if wait > self.policy.max_wait {
+                        break (response, limit, permit);
+                    }
+
+                    // let other requests use the slot in the meantime
+                    drop(permit);
+                    tokio::time::sleep(wait).await;
+
+                    debug!(retries, "retrying rate limited request");
+
+                    retries += 1;
+                    request = next;
+                }
+
+                _ => break (response, limit, permit),
+            }
+        };
 
         let headers = response.headers();
-        let limit = RateLimit::new(headers);
 
-        if let Some(cb) = self.response_cb.take() {
-            cb(headers);
-        }
+        let meta = ResponseMeta::new(response.status(), headers);
+        self.meta = Some(meta.clone());
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        let retry_after = retry_after(headers);
 
         let status = response.status();
         let text = response.text().await?;
 
-        debug!(status = status.as_u16(), response = text);
+        debug!(
+            status = status.as_u16(),
+            bytes = text.len(),
+            response = text
+        );
 
-        if !text.is_json() {
-            return Err(ApiError::ApiError {
-                status,
-                error: text,
-            });
+        // successful responses without a body (e.g. 204 on PUT/DELETE) are returned as is.
+        // deserializing treats them as null
+        let is_empty = text.trim().is_empty();
+
+        if !status.is_success() || (json && !is_empty && !text.is_json()) {
+            // non-json bodies can be entire html error pages, so keep only the gist of them
+            let error = if text.is_json() {
+                text
+            } else {
+                summarize_body(&text, content_type.as_deref())
+            };
+
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                return Err(ApiError::RateLimited { retry_after, error });
+            }
+
+            return Err(ApiError::ApiError { status, error });
         }
 
-        let data = serde_json::from_str(&text)?;
+        Ok((limit.unwrap_or_else(RateLimit::unknown), meta, text))
+    }
+}
+
+/// Deserialize a response body
+pub(crate) fn deserialize<'de, D: Deserialize<'de>>(text: &'de str) -> Result<D, ApiError> {
+    // an empty body is null, which deserializes fine into `()` and `Option<T>`
+    let text = if text.trim().is_empty() { "null" } else { text };
+
+    // the path tells which field failed, which line/column alone doesn't in the large anime objects
+    let deserializer = &mut serde_json::Deserializer::from_str(text);
+
+    serde_path_to_error::deserialize(deserializer).map_err(|e| ApiError::Deserialize {
+        path: e.path().to_string(),
+        source: e.into_inner(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        test_server::{response, serve_once},
+        AnimeScheduleBuilder, AnimeScheduleClient,
+    };
+
+    #[tokio::test]
+    async fn put_accepts_no_content() {
+        let (url, server) = serve_once(response("204 No Content", &[], "")).await;
+        let mut http = AnimeScheduleClient::test_with_base_url(url).http;
+
+        http.put::<()>(format!("{API_URL}/animelists/oauth/frieren"), true)
+            .await
+            .unwrap();
+
+        assert!(server.await.unwrap().head.starts_with("PUT "));
+    }
+
+    #[tokio::test]
+    async fn delete_accepts_no_content() {
+        let (url, server) = serve_once(response("204 No Content", &[], "")).await;
+        let mut http = AnimeScheduleClient::test_with_base_url(url).http;
+
+        http.delete::<()>(format!("{API_URL}/animelists/oauth/frieren"), true)
+            .await
+            .unwrap();
+
+        assert!(server.await.unwrap().head.starts_with("DELETE "));
+    }
+
+    #[tokio::test]
+    async fn throttle_keeps_the_observed_rate_limit() {
+        let reset = (Utc::now().timestamp() + 60).to_string();
+        let headers = [
+            ("x-ratelimit-limit", "120"),
+            ("x-ratelimit-remaining", "5"),
+            ("x-ratelimit-reset", reset.as_str()),
+        ];
+        let (url, server) = serve_once(response("200 OK", &headers, "{}")).await;
+
+        let auth = AnimeScheduleClient::test().auth;
+        let policy = RateLimitPolicy::default();
+        let mut http = ApiRequest::new(auth, Client::new(), policy, true, None, false, Some(url));
+
+        http.get::<serde_json::Value>(format!("{API_URL}/anime"), false)
+            .await
+            .unwrap();
+        server.await.unwrap();
+
+        // a request is taken from the bucket, but the observed rate limit is what the server sent
+        http.take_request().await;
+
+        let throttle = http.throttle.as_ref().unwrap();
+        assert_eq!(throttle.remaining(), Some(4));
+        assert_eq!(http.rate_limit().unwrap().remaining, 5);
+    }
+
+    #[tokio::test]
+    async fn download_follows_redirects_when_api_requests_dont() {
+        let (cdn, cdn_server) =
+            serve_once(response("200 OK", &[("Content-Type", "image/png")], "png")).await;
+        let location = cdn.join("avatar.png").unwrap();
+        let (url, server) = serve_once(response(
+            "302 Found",
+            &[("Location", location.as_str())],
+            "",
+        ))
+        .await;
+
+        let client = AnimeScheduleBuilder::new()
+            .auth_shared(AnimeScheduleClient::test().auth)
+            .follow_redirects(false)
+            .build()
+            .unwrap();
+
+        let (content_type, data) = client
+            .http
+            .download(url.join("avatar").unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+        assert_eq!(&data[..], b"png");
+        assert!(server.await.unwrap().head.starts_with("GET /avatar "));
+        assert!(cdn_server
+            .await
+            .unwrap()
+            .head
+            .starts_with("GET /avatar.png "));
+    }
+
+    #[tokio::test]
+    async fn missing_rate_limit_is_unknown() {
+        let (url, server) = serve_once(response("200 OK", &[], "{}")).await;
+        let mut http = AnimeScheduleClient::test_with_base_url(url).http;
+
+        let (limit, _) = http
+            .get::<serde_json::Value>(format!("{API_URL}/anime"), false)
+            .await
+            .unwrap();
+        server.await.unwrap();
+
+        assert!(limit.unknown && limit.partial);
+        assert!(http.rate_limit().is_none());
+
+        // e.g. restored as the pacing state
+        http.set_rate_limit(limit);
+        assert!(http.rate_limit().is_none());
+    }
+
+    #[tokio::test]
+    async fn raw_requests_keep_an_empty_body() {
+        let (url, server) = serve_once(response("200 OK", &[], "")).await;
+        let mut http = AnimeScheduleClient::test_with_base_url(url).http;
+
+        let (_, text) = http
+            .get_raw(format!("{API_URL}/users/frieren/avatar"), false)
+            .await
+            .unwrap();
 
-        Ok((limit.unwrap(), data))
+        assert_eq!(text, "");
+        server.await.unwrap();
     }
 }
@@ -1,14 +1,32 @@
-use std::{fmt, future::Future, pin::Pin, sync::Mutex, time::Duration};
+#[cfg(feature = "loopback-callback")]
+mod loopback;
+mod store;
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use chrono::Utc;
 use oauth2::{
-    basic::BasicClient, reqwest::async_http_client, AccessToken, AuthUrl, AuthorizationCode,
-    ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, RedirectUrl, RefreshToken, RevocationUrl,
-    Scope, TokenResponse as _, TokenUrl,
+    basic::{BasicClient, BasicTokenResponse},
+    reqwest::async_http_client,
+    AccessToken, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    RedirectUrl, RefreshToken, RevocationUrl, Scope, TokenResponse as _, TokenUrl,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{errors::TokenError, API_URL, RUNTIME};
+use crate::{
+    errors::{ApiError, TokenError},
+    BlockingRuntime, API_URL,
+};
+
+#[cfg(feature = "loopback-callback")]
+pub use loopback::loopback_callback;
+pub use store::{FileTokenStore, TokenStore};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
@@ -59,15 +77,21 @@ pub type Callback = Box<
         + 'static,
 >;
 
-/// A (de)serializable version of [Auth]. Only serializes the access token and its expiry.
+/// A (de)serializable version of [Auth]. Only serializes the access and refresh tokens, and their expiry.
 /// This can be converted back to [Auth] if you provide your id, secret, app_token, and redirect url.
 ///
 /// Callbacks are not saved or converted back. You must set it again manually.
+///
+/// The serialized form contains the token secrets in plain text so they can be restored, so store it securely.
+/// Only the [Debug] output is redacted.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthTokens {
     pub access_token: AccessToken,
     pub refresh_token: RefreshToken,
     pub expires_at: u64,
+    /// When the refresh token expires. Tokens saved before this existed have 0, which falls back to `expires_at`
+    #[serde(default)]
+    pub refresh_expires_at: u64,
 }
 
 impl Default for AuthTokens {
@@ -76,6 +100,7 @@ impl Default for AuthTokens {
             access_token: AccessToken::new(String::new()),
             refresh_token: RefreshToken::new(String::new()),
             expires_at: 0,
+            refresh_expires_at: 0,
         }
     }
 }
@@ -93,6 +118,7 @@ impl AuthTokens {
         auth.set_access_token_unchecked(self.access_token.clone());
         auth.set_refresh_token_unchecked(self.refresh_token.clone());
         auth.set_expires_at_unchecked(self.expires_at);
+        auth.set_refresh_expires_at_unchecked(self.refresh_expires_at());
 
         auth
     }
@@ -107,26 +133,70 @@ impl AuthTokens {
         let auth = Auth::new(client_id, client_secret, app_token, redirect_url);
 
         auth.set_access_token_unchecked(self.access_token);
+        auth.set_refresh_expires_at_unchecked(self.refresh_expires_at());
         auth.set_refresh_token_unchecked(self.refresh_token);
         auth.set_expires_at_unchecked(self.expires_at);
 
         auth
     }
+
+    fn refresh_expires_at(&self) -> u64 {
+        match self.refresh_expires_at {
+            0 => self.expires_at,
+            at => at,
+        }
+    }
+}
+
+/// The default of [`Auth::set_expiry_skew()`]
+pub const DEFAULT_EXPIRY_SKEW: Duration = Duration::from_secs(30);
+
+/// The oauth2 scope needed to modify a user's anime list
+pub const SCOPE_ANIMELIST: &str = "animelist";
+
+/// The outcome of [`Auth::refresh_if_expiring()`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    /// The tokens were refreshed
+    Refreshed,
+    /// The access token doesn't expire within the margin, so nothing was done
+    NotNeeded,
+}
+
+/// A snapshot of which tokens are still valid. See [`Auth::token_validity()`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TokenValidity {
+    /// Whether the access token is still valid. If not, try to refresh it
+    pub access_valid: bool,
+    /// Whether the refresh token is still valid. If not, the user needs to regenerate their tokens
+    pub refresh_valid: bool,
 }
 
 /// Manages oauth2 and client id, client secret, and app_token
 ///
-/// Note that both access and refresh tokens are only valid for 3600 after issuance
+/// Note that both access and refresh tokens are currently only valid for 3600 after issuance
 pub struct Auth {
     client: BasicClient,
     app_token: AppToken,
     access_token: Mutex<AccessToken>,
     refresh_token: Mutex<RefreshToken>,
-    // time in utc seconds when access and refresh token will expire
+    // time in utc seconds when the access token will expire
     // current api expiration is now + 3600
     expires_at: Mutex<u64>,
+    // time in utc seconds when the refresh token will expire
+    refresh_expires_at: Mutex<u64>,
+    // how long newly issued refresh tokens are valid, if known
+    refresh_lifetime: Mutex<Option<Duration>>,
+    // how long before their expiry tokens are considered expired
+    expiry_skew: Mutex<Duration>,
     scopes: Mutex<Vec<Scope>>,
-    callback: tokio::sync::Mutex<Callback>,
+    // the id of the user the access token belongs to, once it was fetched
+    user_id: Mutex<Option<String>>,
+    callback: tokio::sync::Mutex<Option<Callback>>,
+    // held while checking for and doing a refresh, so concurrent requests refresh only once
+    refresh_lock: tokio::sync::Mutex<()>,
+    store: Mutex<Option<Arc<dyn TokenStore>>>,
+    runtime: Mutex<BlockingRuntime>,
 }
 
 impl fmt::Debug for Auth {
@@ -139,8 +209,10 @@ impl fmt::Debug for Auth {
             .field("access_token", &"[redacted]")
             .field("refresh_token", &"[redacted]")
             .field("expires_at", &"[redacted]")
+            .field("refresh_expires_at", &"[redacted]")
             .field("scopes", &"[redacted]")
             .field("callback", &"<ptr>")
+            .field("store", &"<ptr>")
             .finish()
     }
 }
@@ -152,14 +224,38 @@ impl Auth {
         app_token: AppToken,
         redirect_uri: RedirectUrl,
     ) -> Self {
+        Self::new_with_base(client_id, client_secret, app_token, redirect_uri, API_URL)
+    }
+
+    /// Like [`Self::new()`], but for an api at another base url (e.g. a mock server in tests).
+    /// The oauth2 endpoints are expected under it, like `{base_url}/oauth2/token`.
+    pub fn with_base_url(
+        client_id: ClientId,
+        client_secret: ClientSecret,
+        app_token: AppToken,
+        redirect_uri: RedirectUrl,
+        base_url: &reqwest::Url,
+    ) -> Self {
+        let base_url = base_url.as_str().trim_end_matches('/');
+        Self::new_with_base(client_id, client_secret, app_token, redirect_uri, base_url)
+    }
+
+    fn new_with_base(
+        client_id: ClientId,
+        client_secret: ClientSecret,
+        app_token: AppToken,
+        redirect_uri: RedirectUrl,
+        base_url: &str,
+    ) -> Self {
+        // the base is a valid url, so these are too
         let client = BasicClient::new(
             client_id.clone(),
             Some(client_secret.clone()),
-            AuthUrl::new(format!("{API_URL}/oauth2/authorize")).unwrap(),
-            Some(TokenUrl::new(format!("{API_URL}/oauth2/token")).unwrap()),
+            AuthUrl::new(format!("{base_url}/oauth2/authorize")).unwrap(),
+            Some(TokenUrl::new(format!("{base_url}/oauth2/token")).unwrap()),
         )
         .set_redirect_uri(redirect_uri.clone())
-        .set_revocation_uri(RevocationUrl::new(format!("{API_URL}/oauth2/revoke")).unwrap());
+        .set_revocation_uri(RevocationUrl::new(format!("{base_url}/oauth2/revoke")).unwrap());
 
         Self {
             client,
@@ -167,29 +263,44 @@ impl Auth {
             access_token: Mutex::new(AccessToken::new(String::new())),
             refresh_token: Mutex::new(RefreshToken::new(String::new())),
             expires_at: Mutex::new(0),
+            refresh_expires_at: Mutex::new(0),
+            refresh_lifetime: Mutex::new(None),
+            expiry_skew: Mutex::new(DEFAULT_EXPIRY_SKEW),
             scopes: Mutex::new(Vec::new()),
+            user_id: Mutex::new(None),
 
-            callback: tokio::sync::Mutex::new(Box::new(|_, _| {
-                unimplemented!("oauth2 callback not implemented")
-            })),
+            callback: tokio::sync::Mutex::new(None),
+            refresh_lock: tokio::sync::Mutex::new(()),
+            store: Mutex::new(None),
+            runtime: Mutex::default(),
         }
     }
 
     /// Return client tokens to save user creds that can be serialized/deserialized.
-    /// serializes access/refresh tokens, and their expiry
+    /// serializes access/refresh tokens, and their expiries
     /// Does not serialize client_id, client_secret, scopes, or callback
     pub fn to_tokens(&self) -> AuthTokens {
         let at = self.access_token();
         let rt = self.refresh_token();
         let ea = self.expires_at();
+        let rea = self.refresh_expires_at();
 
         AuthTokens {
             access_token: at,
             refresh_token: rt,
             expires_at: ea,
+            refresh_expires_at: rea,
         }
     }
 
+    /// The inner oauth2 client, for oauth2 operations this crate doesn't wrap.
+    ///
+    /// This is unstable. The oauth2 version is an implementation detail and may change in any release.
+    #[cfg(feature = "oauth2-internals")]
+    pub fn oauth_client(&self) -> &BasicClient {
+        &self.client
+    }
+
     /// Get the app token that was saved into this.
     pub fn app_token(&self) -> AppToken {
         self.app_token.clone()
@@ -199,7 +310,7 @@ impl Auth {
     ///
     /// This method is safe in terms of no UB, however it is unchecked because it is possible to cause inconsistent state.
     ///
-    /// Caller agrees to also set the correct access token expiry time as well.
+    /// Caller agrees to also set the correct refresh token expiry time as well.
     pub fn set_refresh_token_unchecked(&self, token: RefreshToken) {
         let mut lock = self.refresh_token.lock().unwrap();
         *lock = token;
@@ -210,9 +321,35 @@ impl Auth {
     /// This method is safe in terms of no UB, however it is unchecked because it is possible to cause inconsistent state.
     ///
     /// Caller agrees to also set the correct access token expiry time as well.
+    ///
+    /// The token may belong to another user, so this clears the cached user id.
     pub fn set_access_token_unchecked(&self, token: AccessToken) {
         let mut lock = self.access_token.lock().unwrap();
         *lock = token;
+        drop(lock);
+
+        self.clear_user_id();
+    }
+
+    /// Forget the cached id of the current user, so [`AnimeScheduleClient::current_user_id()`](crate::AnimeScheduleClient::current_user_id)
+    /// fetches it again.
+    ///
+    /// This is done automatically whenever the access token changes or is revoked.
+    pub fn clear_user_id(&self) {
+        *self.user_id.lock().unwrap() = None;
+    }
+
+    pub(crate) fn user_id(&self) -> Option<String> {
+        self.user_id.lock().unwrap().clone()
+    }
+
+    /// Cache the current user's id, unless the access token changed since it was fetched
+    pub(crate) fn set_user_id(&self, user_id: String, token: &AccessToken) {
+        let access_token = self.access_token.lock().unwrap();
+
+        if access_token.secret() == token.secret() {
+            *self.user_id.lock().unwrap() = Some(user_id);
+        }
     }
 
     /// Updates the access token expiry time
@@ -227,12 +364,111 @@ impl Auth {
         *lock = expiry;
     }
 
+    /// Updates the refresh token expiry time
+    pub fn set_refresh_expires_in_unchecked(&self, duration: Duration) {
+        let mut lock = self.refresh_expires_at.lock().unwrap();
+        *lock = Utc::now().timestamp() as u64 + duration.as_secs();
+    }
+
+    /// Updates the refresh token expiry time
+    pub fn set_refresh_expires_at_unchecked(&self, expiry: u64) {
+        let mut lock = self.refresh_expires_at.lock().unwrap();
+        *lock = expiry;
+    }
+
+    /// Consider tokens expired this long before they actually expire, so requests sent right before the expiry
+    /// don't fail on the way. Defaults to [DEFAULT_EXPIRY_SKEW].
+    ///
+    /// This affects [`Self::is_valid()`], [`Self::is_refresh_valid()`], and [`Self::token_validity()`].
+    pub fn set_expiry_skew(&self, skew: Duration) {
+        *self.expiry_skew.lock().unwrap() = skew;
+    }
+
+    /// How long before their expiry tokens are considered expired
+    pub fn expiry_skew(&self) -> Duration {
+        *self.expiry_skew.lock().unwrap()
+    }
+
+    /// How long the refresh tokens issued by [`Self::refresh()`] and [`Self::regenerate()`] are valid.
+    ///
+    /// The token response only has the access token's lifetime. Without this, a new refresh token keeps the
+    /// current refresh token's expiry if that's later than the new access token's, and otherwise gets the access
+    /// token's expiry.
+    pub fn set_refresh_token_lifetime(&self, lifetime: Duration) {
+        *self.refresh_lifetime.lock().unwrap() = Some(lifetime);
+    }
+
+    /// How long newly issued refresh tokens are valid, if set with [`Self::set_refresh_token_lifetime()`]
+    pub fn refresh_token_lifetime(&self) -> Option<Duration> {
+        *self.refresh_lifetime.lock().unwrap()
+    }
+
+    /// The runtime the blocking methods run on, e.g. your app's [`Handle::current()`](tokio::runtime::Handle::current).
+    /// This is set by [`AnimeScheduleBuilder::runtime()`](crate::AnimeScheduleBuilder::runtime).
+    ///
+    /// By default a current-thread runtime shared by everything in this crate is created when it's first needed.
+    ///
+    /// The handle must belong to a multi-thread runtime which the thread calling the blocking methods
+    /// isn't driving, otherwise they hang or panic. See [`AnimeScheduleBuilder::runtime()`](crate::AnimeScheduleBuilder::runtime).
+    pub fn set_runtime_handle(&self, handle: tokio::runtime::Handle) {
+        self.set_runtime(BlockingRuntime(Some(handle)));
+    }
+
+    pub(crate) fn set_runtime(&self, runtime: BlockingRuntime) {
+        *self.runtime.lock().unwrap() = runtime;
+    }
+
+    fn runtime(&self) -> BlockingRuntime {
+        self.runtime.lock().unwrap().clone()
+    }
+
+    /// Save the tokens to the store after every successful [`Self::refresh()`] and [`Self::regenerate()`].
+    /// Replaces any previously attached store.
+    ///
+    /// This doesn't load the stored tokens. Restore them with [`AuthTokens::into_auth()`] first.
+    pub fn attach_store(&self, store: impl TokenStore + 'static) {
+        *self.store.lock().unwrap() = Some(Arc::new(store));
+    }
+
     /// Add an oauth2 scope. Use this before you generate a new token.
     pub fn add_scope(&self, scope: Scope) {
         let mut lock = self.scopes.lock().unwrap();
         lock.push(scope);
     }
 
+    /// Replace all oauth2 scopes. Use this before you generate a new token.
+    pub fn set_scopes(&self, scopes: impl IntoIterator<Item = Scope>) {
+        let mut lock = self.scopes.lock().unwrap();
+        *lock = scopes.into_iter().collect();
+    }
+
+    /// Remove all oauth2 scopes. Use this before you generate a new token.
+    pub fn clear_scopes(&self) {
+        self.scopes.lock().unwrap().clear();
+    }
+
+    /// The oauth2 scopes which are requested when generating a new token
+    pub fn scopes(&self) -> Vec<Scope> {
+        self.scopes.lock().unwrap().clone()
+    }
+
+    /// Whether an oauth2 scope has been added. Use this to check for a needed scope before you generate a new token.
+    pub fn has_scope(&self, scope: &Scope) -> bool {
+        self.scopes.lock().unwrap().contains(scope)
+    }
+
+    /// Best-effort check that a scope was added. Passes if no scopes were added at all,
+    /// since the added scopes don't necessarily reflect what the token was granted.
+    pub(crate) fn require_scope(&self, scope: &str) -> Result<(), ApiError> {
+        let scopes = self.scopes.lock().unwrap();
+
+        if scopes.is_empty() || scopes.iter().any(|s| s.as_str() == scope) {
+            Ok(())
+        } else {
+            Err(ApiError::MissingScope(scope.to_owned()))
+        }
+    }
+
     /// Set the callback used when running [`Self::regenerate()`].
     /// This passes in a [`CsrfToken`] representing the client state this callback is looking for.
     /// You can know which client request is the correct client because the states match each other.
@@ -249,7 +485,8 @@ impl Auth {
         f: F,
     ) {
         let mut lock = self.callback.lock().await;
-        *lock = Box::new(move |url, state| Box::pin(f(url, state)));
+        let callback: Callback = Box::new(move |url, state| Box::pin(f(url, state)));
+        *lock = Some(callback);
     }
 
     pub fn set_callback_blocking<
@@ -261,7 +498,7 @@ impl Auth {
         &self,
         f: F,
     ) {
-        RUNTIME.block_on(self.set_callback(f))
+        self.runtime().block_on(self.set_callback(f))
     }
 
     /// Is the access token valid?
@@ -272,7 +509,7 @@ impl Auth {
     ///
     /// (Manual setup is, for example, manually setting the access token)
     pub fn is_valid(&self) -> bool {
-        (Utc::now().timestamp() as u64) < *self.expires_at.lock().unwrap()
+        self.skewed_now() < *self.expires_at.lock().unwrap()
     }
 
     /// Is the refresh token valid?
@@ -283,7 +520,21 @@ impl Auth {
     ///
     /// (Manual setup is, for example, manually setting the refresh token)
     pub fn is_refresh_valid(&self) -> bool {
-        (Utc::now().timestamp() as u64) < *self.expires_at.lock().unwrap()
+        self.skewed_now() < *self.refresh_expires_at.lock().unwrap()
+    }
+
+    /// Whether the access and refresh tokens are valid, checked at the same moment.
+    ///
+    /// Use this to decide between refreshing and regenerating the tokens.
+    pub fn token_validity(&self) -> TokenValidity {
+        let now = self.skewed_now();
+        let expires_at = *self.expires_at.lock().unwrap();
+        let refresh_expires_at = *self.refresh_expires_at.lock().unwrap();
+
+        TokenValidity {
+            access_valid: now < expires_at,
+            refresh_valid: now < refresh_expires_at,
+        }
     }
 
     /// Revoke the access token
@@ -299,12 +550,14 @@ impl Auth {
             .await
             .map_err(|e| TokenError::Revoke(e.to_string()))?;
 
+        self.clear_user_id();
+
         Ok(())
     }
 
     /// Revoke the access token
     pub fn revoke_token_blocking(&self) -> Result<(), TokenError> {
-        RUNTIME.block_on(self.revoke_token())
+        self.runtime().block_on(self.revoke_token())
     }
 
     /// Revoke the refresh token
@@ -325,7 +578,54 @@ impl Auth {
 
     /// Revoke the refresh token
     pub fn revoke_refresh_token_blocking(&self) -> Result<(), TokenError> {
-        RUNTIME.block_on(self.revoke_refresh_token())
+        self.runtime().block_on(self.revoke_refresh_token())
+    }
+
+    /// Revoke both tokens, e.g. to log the user out. On success the tokens and their expiry are cleared,
+    /// and saved to the store if one is attached.
+    ///
+    /// Both revocations are always attempted. If either fails, [`TokenError::RevokePartial`] tells which succeeded,
+    /// so the other can be retried with [`Self::revoke_token()`] or [`Self::revoke_refresh_token()`].
+    pub async fn revoke_all(&self) -> Result<(), TokenError> {
+        let access = self.revoke_token().await;
+        let refresh = self.revoke_refresh_token().await;
+
+        match (access, refresh) {
+            (Ok(()), Ok(())) => (),
+
+            (access, refresh) => {
+                let error = [&access, &refresh]
+                    .into_iter()
+                    .filter_map(|result| result.as_ref().err())
+                    // the revoke error's message alone doesn't say why
+                    .map(|e| match e {
+                        TokenError::Revoke(reason) => reason.clone(),
+                        e => e.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                return Err(TokenError::RevokePartial {
+                    access_revoked: access.is_ok(),
+                    refresh_revoked: refresh.is_ok(),
+                    error,
+                });
+            }
+        }
+
+        // empty tokens, which expired long ago
+        self.apply_tokens(&AuthTokens::default());
+
+        Ok(())
+    }
+
+    /// Revoke both tokens, e.g. to log the user out. On success the tokens and their expiry are cleared,
+    /// and saved to the store if one is attached.
+    ///
+    /// Both revocations are always attempted. If either fails, [`TokenError::RevokePartial`] tells which succeeded,
+    /// so the other can be retried with [`Self::revoke_token()`] or [`Self::revoke_refresh_token()`].
+    pub fn revoke_all_blocking(&self) -> Result<(), TokenError> {
+        self.runtime().block_on(self.revoke_all())
     }
 
     /// Automatically regnerate token
@@ -350,7 +650,7 @@ impl Auth {
     ///
     /// Note that both access and refresh tokens are only valid for 3600
     pub fn try_refresh_blocking(&self) -> Result<(), TokenError> {
-        RUNTIME.block_on(self.try_refresh())
+        self.runtime().block_on(self.try_refresh())
     }
 
     /// Get access token
@@ -363,13 +663,63 @@ impl Auth {
         self.refresh_token.lock().unwrap().clone()
     }
 
-    /// time in utc seconds when access and refresh token expires
+    /// time in utc seconds when the access token expires
     pub fn expires_at(&self) -> u64 {
         *self.expires_at.lock().unwrap()
     }
 
+    /// time in utc seconds when the refresh token expires
+    pub fn refresh_expires_at(&self) -> u64 {
+        *self.refresh_expires_at.lock().unwrap()
+    }
+
+    /// How long until the access token expires. Zero if it already expired.
+    pub fn expires_in(&self) -> Duration {
+        let now = Utc::now().timestamp() as u64;
+        Duration::from_secs(self.expires_at().saturating_sub(now))
+    }
+
+    /// Refresh the tokens if the access token expires within the margin.
+    ///
+    /// Use this in keep-alive loops to refresh e.g. a minute before expiry, rather than waiting for a request to fail.
+    ///
+    /// Fails with [`TokenError::Expired`] if a refresh is needed but the refresh token isn't valid anymore.
+    pub async fn refresh_if_expiring(
+        &self,
+        margin: Duration,
+    ) -> Result<RefreshOutcome, TokenError> {
+        // a refresh rotates the refresh token, so a second one at the same time would use an already spent token
+        let _guard = self.refresh_lock.lock().await;
+
+        if self.expires_in() > margin {
+            return Ok(RefreshOutcome::NotNeeded);
+        }
+
+        if !self.is_refresh_valid() {
+            return Err(TokenError::Expired);
+        }
+
+        self.refresh().await?;
+
+        Ok(RefreshOutcome::Refreshed)
+    }
+
+    /// Refresh the tokens if the access token expires within the margin.
+    ///
+    /// Use this in keep-alive loops to refresh e.g. a minute before expiry, rather than waiting for a request to fail.
+    ///
+    /// Fails with [`TokenError::Expired`] if a refresh is needed but the refresh token isn't valid anymore.
+    pub fn refresh_if_expiring_blocking(
+        &self,
+        margin: Duration,
+    ) -> Result<RefreshOutcome, TokenError> {
+        self.runtime().block_on(self.refresh_if_expiring(margin))
+    }
+
     /// exchange refresh token for new access token
-    pub async fn refresh(&self) -> Result<(), TokenError> {
+    ///
+    /// Returns the new tokens, which are the same as those saved to the store.
+    pub async fn refresh(&self) -> Result<AuthTokens, TokenError> {
         let token = self.refresh_token.lock().unwrap().clone();
 
         let token = self
@@ -379,23 +729,20 @@ impl Auth {
             .await
             .map_err(|e| TokenError::OAuth2(e.to_string()))?;
 
-        self.set_access_token_unchecked(token.access_token().clone());
-
-        self.set_refresh_token_unchecked(token.refresh_token().unwrap().clone());
+        let tokens = self.issued_tokens(&token);
+        self.apply_tokens(&tokens);
 
-        self.set_expires_at_unchecked(
-            (Utc::now().timestamp() as u64) + token.expires_in().unwrap().as_secs(),
-        );
-
-        Ok(())
+        Ok(tokens)
     }
 
-    pub fn refresh_blocking(&self) -> Result<(), TokenError> {
-        RUNTIME.block_on(self.refresh())
+    pub fn refresh_blocking(&self) -> Result<AuthTokens, TokenError> {
+        self.runtime().block_on(self.refresh())
     }
 
     /// regenerate fresh access and refresh tokens
-    pub async fn regenerate(&self) -> Result<(), TokenError> {
+    ///
+    /// Returns the new tokens, which are the same as those saved to the store.
+    pub async fn regenerate(&self) -> Result<AuthTokens, TokenError> {
         let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
         let scopes = self.scopes.lock().unwrap().clone();
@@ -408,6 +755,10 @@ impl Auth {
             .url();
 
         let callback = self.callback.lock().await;
+        let Some(callback) = callback.as_ref() else {
+            return Err(TokenError::CallbackUnset);
+        };
+
         let (auth_code, client_state) = match callback(auth_url, state.clone()).await {
             Ok(v) => v,
             Err(e) => return Err(TokenError::Callback(e.to_string())),
@@ -429,18 +780,160 @@ impl Auth {
             return Err(TokenError::Access);
         };
 
-        self.set_expires_at_unchecked(
-            Utc::now().timestamp() as u64 + token.expires_in().unwrap().as_secs(),
+        let tokens = self.issued_tokens(&token);
+        self.apply_tokens(&tokens);
+
+        Ok(tokens)
+    }
+
+    pub fn regenerate_blocking(&self) -> Result<AuthTokens, TokenError> {
+        self.runtime().block_on(self.regenerate())
+    }
+
+    /// Set the tokens and their expiry, and save them to the store if one is attached
+    fn apply_tokens(&self, tokens: &AuthTokens) {
+        self.set_access_token_unchecked(tokens.access_token.clone());
+        self.set_refresh_token_unchecked(tokens.refresh_token.clone());
+        self.set_expires_at_unchecked(tokens.expires_at);
+        self.set_refresh_expires_at_unchecked(tokens.refresh_expires_at);
+
+        // cloned out, so a slow store doesn't hold the lock
+        let store = self.store.lock().unwrap().clone();
+
+        if let Some(store) = store {
+            // saved as given, since reading them back could already see a concurrent refresh
+            store.save(tokens);
+        }
+    }
+
+    /// The current time in utc seconds, plus the expiry skew
+    fn skewed_now(&self) -> u64 {
+        (Utc::now().timestamp() as u64).saturating_add(self.expiry_skew().as_secs())
+    }
+
+    /// The tokens of a token response
+    fn issued_tokens(&self, token: &BasicTokenResponse) -> AuthTokens {
+        let now = Utc::now().timestamp() as u64;
+        let expires_at = now + token.expires_in().unwrap().as_secs();
+
+        // the token response only has the access token's lifetime
+        let refresh_expires_at = match self.refresh_token_lifetime() {
+            Some(lifetime) => now.saturating_add(lifetime.as_secs()),
+            None => self.refresh_expires_at().max(expires_at),
+        };
+
+        AuthTokens {
+            access_token: token.access_token().clone(),
+            refresh_token: token.refresh_token().unwrap().clone(),
+            expires_at,
+            refresh_expires_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_server::{response, serve_once},
+        AnimeScheduleClient,
+    };
+
+    #[test]
+    fn tokens_round_trip() {
+        let tokens = AuthTokens {
+            access_token: AccessToken::new("access-secret".to_owned()),
+            refresh_token: RefreshToken::new("refresh-secret".to_owned()),
+            expires_at: 1_700_000_000,
+            refresh_expires_at: 1_800_000_000,
+        };
+
+        let json = serde_json::to_string(&tokens).unwrap();
+        let restored = serde_json::from_str::<AuthTokens>(&json).unwrap();
+
+        assert_eq!(restored.access_token.secret(), "access-secret");
+        assert_eq!(restored.refresh_token.secret(), "refresh-secret");
+        assert_eq!(restored.expires_at, 1_700_000_000);
+        assert_eq!(restored.refresh_expires_at, 1_800_000_000);
+
+        let debug = format!("{restored:?}");
+        assert!(!debug.contains("access-secret"));
+        assert!(!debug.contains("refresh-secret"));
+    }
+    #[tokio::test]
+    async fn refresh_token_outlives_access_token() {
+        let body = r#"{
+            "access_token": "access",
+            "token_type": "bearer",
+            "expires_in": 0,
+            "refresh_token": "refresh"
+        }"#;
+        let (url, server) = serve_once(response(
+            "200 OK",
+            &[("Content-Type", "application/json")],
+            body,
+        ))
+        .await;
+
+        let auth = Auth::with_base_url(
+            ClientId::new("test".to_owned()),
+            ClientSecret::new("test".to_owned()),
+            AppToken::new("test".to_owned()),
+            RedirectUrl::new("http://localhost".to_owned()).unwrap(),
+            &url,
         );
+        auth.set_refresh_token_lifetime(Duration::from_secs(24 * 60 * 60));
 
-        self.set_access_token_unchecked(token.access_token().clone());
+        auth.refresh().await.unwrap();
 
-        self.set_refresh_token_unchecked(token.refresh_token().unwrap().clone());
+        assert!(server
+            .await
+            .unwrap()
+            .head
+            .starts_with("POST /oauth2/token "));
+        assert!(!auth.is_valid());
+        assert!(auth.is_refresh_valid());
+        assert_eq!(
+            auth.token_validity(),
+            TokenValidity {
+                access_valid: false,
+                refresh_valid: true,
+            }
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn issued_refresh_token_keeps_a_later_expiry() {
+        let auth = AnimeScheduleClient::test().auth;
+        let token = serde_json::from_str::<BasicTokenResponse>(
+            r#"{
+                "access_token": "access",
+                "token_type": "bearer",
+                "expires_in": 3600,
+                "refresh_token": "refresh"
+            }"#,
+        )
+        .unwrap();
+
+        // the test client's refresh token never expires
+        let tokens = auth.issued_tokens(&token);
+        assert_eq!(tokens.refresh_expires_at, u64::MAX);
+        assert!(tokens.expires_at < u64::MAX);
     }
 
-    pub fn regenerate_blocking(&self) -> Result<(), TokenError> {
-        RUNTIME.block_on(self.regenerate())
+    #[test]
+    fn user_id_is_cleared_with_the_access_token() {
+        let auth = AnimeScheduleClient::test().auth;
+        let token = auth.access_token();
+
+        auth.set_user_id("user".to_owned(), &token);
+        assert_eq!(auth.user_id().as_deref(), Some("user"));
+
+        auth.set_access_token_unchecked(AccessToken::new("other".to_owned()));
+        assert_eq!(auth.user_id(), None);
+
+        // fetched with the previous token, so it may be another user's
+        auth.set_user_id("user".to_owned(), &token);
+        assert_eq!(auth.user_id(), None);
     }
 }
@@ -0,0 +1,144 @@
+use std::{sync::Arc, time::Duration};
+
+use oauth2::{AuthorizationCode, CsrfToken};
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::debug;
+
+use super::Callback;
+
+/// The most of a redirect request that's read. Only the request line is needed
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// A callback for [`Auth::set_callback()`](super::Auth::set_callback) which catches the oauth2 redirect itself.
+///
+/// It listens on `127.0.0.1:port` while waiting for the redirect, so the redirect url must be
+/// `http://127.0.0.1:{port}` or `http://localhost:{port}` (any path). `open_url` is given the authorization url,
+/// e.g. to print it or open it in a browser.
+///
+/// Fails if no redirect arrived within the timeout, the user denied access, or the state didn't match.
+/// The listener is closed when it's done either way.
+pub fn loopback_callback(
+    port: u16,
+    timeout: Duration,
+    open_url: impl Fn(&Url) + Send + Sync + 'static,
+) -> Callback {
+    let open_url = Arc::new(open_url);
+
+    Box::new(move |url, state| {
+        let open_url = open_url.clone();
+
+        Box::pin(async move {
+            let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+
+            open_url(&url);
+
+            let result = tokio::time::timeout(timeout, wait_for_redirect(&listener, &state))
+                .await
+                .unwrap_or_else(|_| Err("timed out waiting for the oauth2 redirect".to_owned()));
+
+            Ok::<_, Box<dyn std::error::Error>>(result?)
+        })
+    })
+}
+
+/// Answer requests until one of them is the redirect
+async fn wait_for_redirect(
+    listener: &TcpListener,
+    state: &CsrfToken,
+) -> Result<(AuthorizationCode, CsrfToken), String> {
+    loop {
+        let (mut stream, _) = listener.accept().await.map_err(|e| e.to_string())?;
+
+        let Some(query) = read_query(&mut stream).await else {
+            // e.g. the browser asking for a favicon
+            respond(&mut stream, "404 Not Found", "Not found").await;
+            continue;
+        };
+
+        let param = |name: &str| {
+            query
+                .iter()
+                .find_map(|(key, value)| (key == name).then(|| value.clone()))
+        };
+
+        if let Some(error) = param("error") {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "Authorization failed. You can close this window.",
+            )
+            .await;
+            return Err(format!("authorization failed: {error}"));
+        }
+
+        let (Some(code), Some(client_state)) = (param("code"), param("state")) else {
+            respond(&mut stream, "404 Not Found", "Not found").await;
+            continue;
+        };
+
+        if client_state != *state.secret() {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "Authorization failed. You can close this window.",
+            )
+            .await;
+            return Err("state verification failed".to_owned());
+        }
+
+        respond(
+            &mut stream,
+            "200 OK",
+            "Authorization finished. You can close this window.",
+        )
+        .await;
+
+        return Ok((AuthorizationCode::new(code), CsrfToken::new(client_state)));
+    }
+}
+
+/// The query of a request, or None if it isn't a GET request with one
+async fn read_query(stream: &mut TcpStream) -> Option<Vec<(String, String)>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+
+    while !buf.windows(2).any(|w| w == b"\r\n") && buf.len() < MAX_REQUEST_SIZE {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+
+        buf.extend_from_slice(&chunk[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&buf);
+    let line = request.lines().next()?;
+
+    debug!(request = line, "oauth2 loopback request");
+
+    let mut parts = line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return None;
+    };
+
+    let url = Url::parse(&format!("http://127.0.0.1{target}")).ok()?;
+    url.query()?;
+
+    Some(url.query_pairs().into_owned().collect())
+}
+
+/// Send a minimal html page and close the connection. Failures don't matter, the browser just won't show it
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!("<!DOCTYPE html><html><body><p>{message}</p></body></html>");
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    _ = stream.write_all(response.as_bytes()).await;
+    _ = stream.shutdown().await;
+}
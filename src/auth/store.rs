@@ -0,0 +1,86 @@
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use super::AuthTokens;
+
+/// Persists tokens across restarts. Attach one with [`Auth::attach_store()`](super::Auth::attach_store)
+/// to save the tokens after every successful refresh or regeneration.
+pub trait TokenStore: Send + Sync {
+    /// The saved tokens, if there are any
+    fn load(&self) -> Option<AuthTokens>;
+
+    /// Save the tokens, replacing any previously saved ones
+    fn save(&self, tokens: &AuthTokens);
+}
+
+/// Stores the tokens as json in a file.
+///
+/// The tokens are in plain text, so keep the file somewhere only you can read. On unix it's created with
+/// owner-only permissions.
+#[derive(Debug, Clone)]
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The file the tokens are stored in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&self, tokens: &AuthTokens) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(tokens)?;
+
+        // written next to it first, so a crash midway doesn't leave a truncated file
+        let tmp = self.path.with_extension("tmp");
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&tmp)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> Option<AuthTokens> {
+        let json = match fs::read(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!(path = %self.path.display(), error = %e, "failed to read tokens");
+                return None;
+            }
+        };
+
+        serde_json::from_slice(&json)
+            .inspect_err(
+                |e| warn!(path = %self.path.display(), error = %e, "failed to parse tokens"),
+            )
+            .ok()
+    }
+
+    fn save(&self, tokens: &AuthTokens) {
+        if let Err(e) = self.write(tokens) {
+            warn!(path = %self.path.display(), error = %e, "failed to save tokens");
+        }
+    }
+}
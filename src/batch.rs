@@ -0,0 +1,40 @@
+use std::{future::Future, pin::pin};
+
+use futures_util::{stream, StreamExt as _};
+
+/// How many requests of a batch may be in flight at once
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+/// How a batch operation handles failed requests
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    /// Stop at the first error. Requests which haven't completed yet are cancelled,
+    /// and the error is the last item of the results.
+    FailFast,
+    /// Run every request and return all of their results.
+    #[default]
+    CollectAll,
+}
+
+/// Run the futures with bounded concurrency. Results are returned in the same order as the futures.
+pub(crate) async fn run<T, E, F>(
+    futures: impl IntoIterator<Item = F>,
+    mode: BatchMode,
+) -> Vec<Result<T, E>>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let mut results = Vec::new();
+    let mut stream = pin!(stream::iter(futures).buffered(MAX_CONCURRENT_REQUESTS));
+
+    while let Some(result) = stream.next().await {
+        let failed = result.is_err();
+        results.push(result);
+
+        if failed && mode == BatchMode::FailFast {
+            break;
+        }
+    }
+
+    results
+}
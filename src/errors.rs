@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use http::StatusCode;
 use thiserror::Error;
 
@@ -8,8 +10,16 @@ use crate::auth::ClientError;
 pub enum TokenError {
     #[error("failed to revoke token")]
     Revoke(String),
+    #[error("failed to revoke all tokens: {error}")]
+    RevokePartial {
+        access_revoked: bool,
+        refresh_revoked: bool,
+        error: String,
+    },
     #[error("callback failed")]
     Callback(String),
+    #[error("callback is not set")]
+    CallbackUnset,
     #[error("refresh token is already expired")]
     Expired,
     #[error("{0}")]
@@ -29,20 +39,50 @@ pub enum TokenError {
 pub enum ApiError {
     #[error("{0}")]
     ParseError(#[from] serde_json::Error),
+    #[error("failed to deserialize '{path}': {source}")]
+    Deserialize {
+        /// the path of the field which failed, e.g. `stats.averageScore`
+        path: String,
+        source: serde_json::Error,
+    },
     #[error("access token missing")]
     AccessTokenError,
     #[error("{status}: {error}")]
     ApiError { status: StatusCode, error: String },
+    #[error("rate limited: {error}")]
+    RateLimited {
+        /// how long the server asked to wait before retrying, if it said
+        retry_after: Option<Duration>,
+        error: String,
+    },
     #[error("{0}")]
     Reqwest(#[from] reqwest::Error),
     #[error("api route requires etag")]
     Etag,
     #[error("api requires xml to be set")]
     Xml,
+    #[error("xml file is {size} bytes, but the max is {max}")]
+    XmlTooLarge { size: u64, max: u64 },
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
     #[error("api requires route")]
     Route,
     #[error("api requires user id")]
     UserId,
+    #[error("invalid date")]
+    Date,
+    #[error("mal list import failed: {0}")]
+    ImportFailed(String),
+    #[error("missing oauth2 scope '{0}'")]
+    MissingScope(String),
+    #[error("invalid query: {0}")]
+    Query(String),
+    #[error("invalid parameters: {0}")]
+    Validation(String),
+    #[error("failed to parse url: {0}")]
+    Url(#[from] ::oauth2::url::ParseError),
+    #[error("{0}")]
+    Token(#[from] TokenError),
 }
 
 #[non_exhaustive]
@@ -54,4 +94,8 @@ pub enum BuilderError {
     Client(#[from] ClientError),
     #[error("{0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("invalid header value: {0}")]
+    Header(#[from] http::header::InvalidHeaderValue),
+    #[error("invalid range '{0}', expected min-max (e.g. 0-∞)")]
+    Range(String),
 }
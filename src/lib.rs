@@ -1,19 +1,33 @@
 pub mod api;
 mod api_request;
 pub mod auth;
+pub mod batch;
 pub mod errors;
 pub mod objects;
 pub mod rate_limit;
+pub mod response;
+#[cfg(test)]
+mod test_server;
 mod utils;
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use const_format::formatcp;
 pub use oauth2::{
     AccessToken, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl, RefreshToken,
     Scope,
 };
-use reqwest::{Client, ClientBuilder};
-use tokio::runtime::{Builder, Runtime};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT_LANGUAGE},
+    redirect::Policy,
+    Client, ClientBuilder, Url,
+};
+use tokio::runtime::{Builder, Handle, Runtime};
 
 use crate::{
     api::{
@@ -21,28 +35,112 @@ use crate::{
         timetables::TimetablesApi,
     },
     auth::Auth,
+    objects::{Category, CategoryType},
     utils::LazyLock,
 };
 
-use self::{api_request::ApiRequest, errors::BuilderError};
+use self::{
+    api_request::{ApiRequest, RateLimitPolicy},
+    errors::{ApiError, BuilderError},
+    rate_limit::RateLimit,
+};
 pub use auth::AppToken;
 
-const API_URL: &str = "https://animeschedule.net/api/v3";
+const SITE_URL: &str = "https://animeschedule.net";
+const API_URL: &str = formatcp!("{SITE_URL}/api/v3");
 
+// used by the blocking methods when no runtime was given
 static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
-    Builder::new_multi_thread()
+    Builder::new_current_thread()
         .enable_all()
         .build()
         .expect("Failed building the Runtime")
 });
 
+/// Runs the futures of the blocking methods
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BlockingRuntime(Option<Handle>);
+
+impl BlockingRuntime {
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        match &self.0 {
+            Some(handle) => handle.block_on(future),
+            None => RUNTIME.block_on(future),
+        }
+    }
+}
+
+// the client is commonly shared between tasks and threads (e.g. in web handlers), so it must stay Send + Sync
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<AnimeScheduleClient>();
+    assert_send_sync::<Auth>();
+};
+
 #[derive(Clone)]
 pub struct AnimeScheduleClient {
     http: ApiRequest,
     pub auth: Arc<Auth>,
+    // (category type, route) -> category
+    category_cache: Arc<Mutex<HashMap<(String, String), Category>>>,
+    runtime: BlockingRuntime,
+    // the assumed anime search page size, when a page doesn't show it
+    anime_page_size: u64,
 }
 
 impl AnimeScheduleClient {
+    /// The runtime for the blocking methods
+    pub(crate) fn runtime(&self) -> BlockingRuntime {
+        self.runtime.clone()
+    }
+
+    /// Create a new [AnimeScheduleBuilder]
+    pub fn builder() -> AnimeScheduleBuilder {
+        AnimeScheduleBuilder::new()
+    }
+
+    /// A client for tests, with dummy credentials and a fake access token that expires far in the future,
+    /// so no oauth2 flow is needed.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn test() -> Self {
+        Self::test_client(None)
+    }
+
+    /// Like [`Self::test()`], but sending all requests to the base url, e.g. a mock server.
+    /// See [`AnimeScheduleBuilder::base_url()`].
+    #[cfg(any(test, feature = "testing"))]
+    pub fn test_with_base_url(base_url: Url) -> Self {
+        Self::test_client(Some(base_url))
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    fn test_client(base_url: Option<Url>) -> Self {
+        let client_id = ClientId::new("test".to_owned());
+        let client_secret = ClientSecret::new("test".to_owned());
+        let app_token = AppToken::new("test".to_owned());
+        let redirect_url = RedirectUrl::new("http://localhost".to_owned()).unwrap();
+
+        let auth = match &base_url {
+            Some(base_url) => {
+                Auth::with_base_url(client_id, client_secret, app_token, redirect_url, base_url)
+            }
+            None => Auth::new(client_id, client_secret, app_token, redirect_url),
+        };
+
+        auth.set_access_token_unchecked(AccessToken::new("test".to_owned()));
+        auth.set_refresh_token_unchecked(RefreshToken::new("test".to_owned()));
+        auth.set_expires_at_unchecked(u64::MAX);
+        auth.set_refresh_expires_at_unchecked(u64::MAX);
+
+        let mut builder = AnimeScheduleBuilder::new().auth(auth);
+        if let Some(base_url) = base_url {
+            builder = builder.base_url(base_url);
+        }
+
+        builder.build().expect("failed to build test client")
+    }
+
     /// Fetch anime data
     pub fn anime(&self) -> AnimeApi {
         AnimeApi::new(self.clone())
@@ -58,6 +156,13 @@ impl AnimeScheduleClient {
         CategoryApi::new(self.clone(), category)
     }
 
+    /// Fetch category data of a known category type.
+    ///
+    /// Use [`Self::categories()`] for category types which aren't in [CategoryType] yet.
+    pub fn categories_typed(&self, category: CategoryType) -> CategoryApi {
+        self.categories(category.into())
+    }
+
     /// Fetch a week's timetable anime
     pub fn timetables(&self) -> TimetablesApi {
         TimetablesApi::new(self.clone())
@@ -67,6 +172,106 @@ impl AnimeScheduleClient {
     pub fn account(&self) -> AccountApi {
         AccountApi::new(self.clone())
     }
+
+    /// The authenticated user's id. It's fetched from the user's anime list on first use,
+    /// then cached in the [Auth], so it's shared between clones of this client.
+    ///
+    /// The cache is cleared whenever the access token changes (e.g. a refresh, regeneration, or revocation).
+    /// Clear it yourself with [`Auth::clear_user_id()`].
+    ///
+    /// Requires the user's oauth2 tokens.
+    pub async fn current_user_id(&self) -> Result<String, ApiError> {
+        if let Some(user_id) = self.auth.user_id() {
+            return Ok(user_id);
+        }
+
+        let token = self.auth.access_token();
+        let (_, list) = self.animelists().get().send().await?;
+
+        self.auth.set_user_id(list.user_id.clone(), &token);
+
+        Ok(list.user_id)
+    }
+
+    /// The authenticated user's id. It's fetched from the user's anime list on first use,
+    /// then cached in the [Auth], so it's shared between clones of this client.
+    ///
+    /// The cache is cleared whenever the access token changes (e.g. a refresh, regeneration, or revocation).
+    /// Clear it yourself with [`Auth::clear_user_id()`].
+    ///
+    /// Requires the user's oauth2 tokens.
+    pub fn current_user_id_blocking(&self) -> Result<String, ApiError> {
+        self.runtime().block_on(self.current_user_id())
+    }
+
+    /// How many more requests may start right now, if [`AnimeScheduleBuilder::max_concurrent_requests()`] was set.
+    pub fn available_request_slots(&self) -> Option<usize> {
+        self.http.available_permits()
+    }
+
+    /// The rate limit seen in the last response of this client (or any of its clones),
+    /// including error responses which had rate limit headers.
+    ///
+    /// Use this to check the remaining budget without threading the [RateLimit] out of every `send()`.
+    pub fn last_rate_limit(&self) -> Option<RateLimit> {
+        self.http.rate_limit()
+    }
+
+    /// An alias of [`Self::last_rate_limit()`], named for saving it.
+    ///
+    /// Save this and restore it with [`Self::restore_pacing_state()`] so a restarted
+    /// service knows how many requests it has left in the current window.
+    pub fn pacing_state(&self) -> Option<RateLimit> {
+        self.last_rate_limit()
+    }
+
+    /// Restore a rate limit saved with [`Self::pacing_state()`].
+    ///
+    /// It's replaced by the rate limit of the next response. [Unknown](RateLimit::unknown) rate limits are ignored.
+    pub fn restore_pacing_state(&self, state: RateLimit) {
+        self.http.set_rate_limit(state);
+    }
+
+    /// Resolve a category's route/slug into the full category (e.g. to get a genre's display name).
+    ///
+    /// Results are cached and shared between clones of this client, so each route is only fetched once.
+    pub async fn resolve_category(
+        &self,
+        category_type: &str,
+        route: &str,
+    ) -> Result<Category, ApiError> {
+        let key = (category_type.to_owned(), route.to_owned());
+
+        if let Some(category) = self.category_cache.lock().unwrap().get(&key) {
+            return Ok(category.clone());
+        }
+
+        let (_, category) = self
+            .categories(category_type)
+            .get()
+            .slug(route)
+            .send()
+            .await?;
+
+        self.category_cache
+            .lock()
+            .unwrap()
+            .insert(key, category.clone());
+
+        Ok(category)
+    }
+
+    /// Resolve a category's route/slug into the full category (e.g. to get a genre's display name).
+    ///
+    /// Results are cached and shared between clones of this client, so each route is only fetched once.
+    pub fn resolve_category_blocking(
+        &self,
+        category_type: &str,
+        route: &str,
+    ) -> Result<Category, ApiError> {
+        self.runtime()
+            .block_on(self.resolve_category(category_type, route))
+    }
 }
 
 /// A builder for [MalClient]
@@ -77,6 +282,17 @@ pub struct AnimeScheduleBuilder {
     client_secret: Option<ClientSecret>,
     app_token: Option<AppToken>,
     redirect_url: Option<RedirectUrl>,
+    language: Option<String>,
+    rate_limit_policy: RateLimitPolicy,
+    throttle: bool,
+    max_concurrent_requests: Option<usize>,
+    follow_redirects: Option<bool>,
+    auto_refresh: bool,
+    base_url: Option<Url>,
+    runtime: Option<Handle>,
+    timeout: Option<Duration>,
+    anime_page_size: Option<u64>,
+    connect_timeout: Option<Duration>,
     #[allow(clippy::complexity)]
     http_cb: Option<Box<dyn FnOnce(ClientBuilder) -> Result<Client, reqwest::Error> + 'static>>,
 }
@@ -126,7 +342,131 @@ impl AnimeScheduleBuilder {
         self
     }
 
+    /// Set the Accept-Language header sent with every request (e.g. `en-US`, `ja`).
+    ///
+    /// The api documentation doesn't list which locales are honored, so this is passed through as-is.
+    /// Content which has no localized version is returned in its default language.
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_owned());
+        self
+    }
+
+    /// Automatically wait for the rate limit to reset when it's exhausted, and retry requests
+    /// which were rate limited anyway. Off by default.
+    ///
+    /// Waits never exceed [`Self::max_rate_limit_wait()`]; if the reset is further away, the request is sent as is.
+    pub fn auto_rate_limit(mut self, enabled: bool) -> Self {
+        self.rate_limit_policy.auto = enabled;
+        self
+    }
+
+    /// The longest to wait for a rate limit reset when [`Self::auto_rate_limit()`] is on. Defaults to 60 seconds.
+    pub fn max_rate_limit_wait(mut self, max_wait: Duration) -> Self {
+        self.rate_limit_policy.max_wait = max_wait;
+        self
+    }
+
+    /// How many times a rate limited request is retried when [`Self::auto_rate_limit()`] is on. Defaults to 3.
+    pub fn max_rate_limit_retries(mut self, retries: u32) -> Self {
+        self.rate_limit_policy.max_retries = retries;
+        self
+    }
+
+    /// Pace requests so this client and all of its clones together stay within the rate limit. Off by default.
+    ///
+    /// Requests are counted against a bucket which is refilled from every rate limit the server sends.
+    /// Once none remain, requests wait until it resets. [`AnimeScheduleClient::last_rate_limit()`] isn't affected,
+    /// it still reports what the server sent.
+    ///
+    /// The bucket is deliberately shared by all endpoints, as the last seen rate limit is, so it's seeded from
+    /// whichever endpoint answered last.
+    pub fn throttle(mut self, enabled: bool) -> Self {
+        self.throttle = enabled;
+        self
+    }
+
+    /// Limit how many requests this client and all of its clones may have in flight at once,
+    /// no matter how many tasks use them. Unlimited by default.
+    ///
+    /// This bounds parallelism, unlike [`Self::throttle()`] which paces requests to the rate limit.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.max_concurrent_requests = Some(max.max(1));
+        self
+    }
+
+    /// Refresh the access token shortly before it expires, before sending an authenticated request. Off by default.
+    ///
+    /// Nothing is done once the refresh token expired too. Those requests are sent as is and fail,
+    /// so the tokens can be regenerated.
+    pub fn auto_refresh(mut self, enabled: bool) -> Self {
+        self.auto_refresh = enabled;
+        self
+    }
+
+    /// Send api and oauth2 requests to another base url, e.g. a mock server in tests.
+    /// Defaults to `https://animeschedule.net/api/v3`.
+    ///
+    /// The oauth2 endpoints are expected under it too, like `{base_url}/oauth2/token`. If you provide your own [Auth],
+    /// make it with [`Auth::with_base_url()`] for those.
+    pub fn base_url(mut self, base_url: Url) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// The runtime the blocking methods run on, e.g. your app's [`Handle::current()`].
+    /// This client's [Auth] uses it too.
+    ///
+    /// By default a current-thread runtime shared by all clients is created when it's first needed.
+    ///
+    /// The handle must belong to a multi-thread runtime which the thread calling the blocking methods
+    /// isn't driving. A current-thread runtime's handle can't drive its io and timers on its own,
+    /// so the blocking methods would hang, and calling them from one of the runtime's own threads panics.
+    ///
+    /// The blocking methods can't be called from within an async context either way, since they block the thread.
+    pub fn runtime(mut self, handle: Handle) -> Self {
+        self.runtime = Some(handle);
+        self
+    }
+
+    /// Whether api requests follow redirects. On by default.
+    ///
+    /// Turn it off to debug unexpected 3xx responses, which are then returned as errors.
+    /// Downloads of avatars and banners still follow redirects, since those are served through a cdn.
+    pub fn follow_redirects(mut self, follow: bool) -> Self {
+        self.follow_redirects = Some(follow);
+        self
+    }
+
+    /// The anime search page size to assume for pagination, where a page doesn't show the actual one.
+    /// Defaults to the documented [ANIME_PER_PAGE](objects::ANIME_PER_PAGE).
+    ///
+    /// Set this if the api changes its page size. A full first page overrides it either way.
+    pub fn anime_page_size(mut self, size: u64) -> Self {
+        self.anime_page_size = Some(size.max(1));
+        self
+    }
+
+    /// The longest a request may take, from connecting until the body was read. No timeout by default.
+    ///
+    /// Timed out requests fail with [ApiError::Reqwest]. This is set on the reqwest client before
+    /// [`Self::http_builder()`]'s callback runs, so a timeout set there takes precedence.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The longest connecting to the server may take. No timeout by default.
+    ///
+    /// This is set on the reqwest client before [`Self::http_builder()`]'s callback runs,
+    /// so a connect timeout set there takes precedence.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
     /// Customize the reqwest client (e.g. change the useragent).
+    ///
+    /// The callback gets a builder with the other options, like [`Self::timeout()`], already applied.
     pub fn http_builder(
         mut self,
         cb: impl FnOnce(ClientBuilder) -> Result<Client, reqwest::Error> + 'static,
@@ -155,14 +495,46 @@ impl AnimeScheduleBuilder {
                 return Err(BuilderError::Builder("redirect_url".to_owned()));
             };
 
-            Arc::new(Auth::new(client_id, client_secret, app_token, redirect_url))
+            let auth = match &self.base_url {
+                Some(base_url) => {
+                    Auth::with_base_url(client_id, client_secret, app_token, redirect_url, base_url)
+                }
+                None => Auth::new(client_id, client_secret, app_token, redirect_url),
+            };
+
+            Arc::new(auth)
         };
 
+        let runtime = BlockingRuntime(self.runtime);
+
+        if runtime.0.is_some() {
+            auth.set_runtime(runtime.clone());
+        }
+
+        let mut builder = ClientBuilder::new();
+
+        if let Some(language) = self.language {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_str(&language)?);
+            builder = builder.default_headers(headers);
+        }
+
+        if self.follow_redirects == Some(false) {
+            builder = builder.redirect(Policy::none());
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
         let http = if let Some(cb) = self.http_cb {
-            let builder = ClientBuilder::new();
             cb(builder)?
         } else {
-            ClientBuilder::new()
+            builder
                 .user_agent(concat!(
                     env!("CARGO_PKG_NAME"),
                     "/",
@@ -171,9 +543,23 @@ impl AnimeScheduleBuilder {
                 .build()?
         };
 
-        let http = ApiRequest::new(auth.clone(), http);
+        let http = ApiRequest::new(
+            auth.clone(),
+            http,
+            self.rate_limit_policy,
+            self.throttle,
+            self.max_concurrent_requests,
+            self.auto_refresh,
+            self.base_url,
+        );
 
-        let mal_client = AnimeScheduleClient { auth, http };
+        let mal_client = AnimeScheduleClient {
+            auth,
+            http,
+            category_cache: Arc::default(),
+            runtime,
+            anime_page_size: self.anime_page_size.unwrap_or(objects::ANIME_PER_PAGE),
+        };
 
         Ok(mal_client)
     }
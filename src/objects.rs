@@ -1,20 +1,40 @@
 mod account;
 mod anime;
+#[cfg(feature = "borrowed")]
+mod borrowed;
 mod lists;
 
 use std::ops::{Deref, DerefMut};
 
 use chrono::{DateTime, FixedOffset};
+use reqwest::Url;
 use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::SITE_URL;
+
 pub use account::*;
 pub use anime::*;
+#[cfg(feature = "borrowed")]
+pub use borrowed::*;
 pub use lists::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(transparent)]
 pub struct Html(pub String);
 
+impl Html {
+    /// The html as plain text. Line breaks and paragraphs become newlines, other tags are removed,
+    /// and the common entities (e.g. `&amp;`, `&lt;`) are decoded.
+    pub fn text(&self) -> String {
+        strip_html(&self.0)
+    }
+
+    /// The raw html
+    pub fn as_raw(&self) -> &str {
+        &self.0
+    }
+}
+
 impl Deref for Html {
     type Target = String;
 
@@ -29,6 +49,63 @@ impl DerefMut for Html {
     }
 }
 
+/// html to plain text. line breaks and paragraphs become newlines, other tags are removed,
+/// and the common entities are decoded
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+
+        let Some(end) = rest[start..].find('>') else {
+            // not a tag, keep it as is
+            text.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+
+        let tag = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if matches!(tag.as_str(), "br" | "p" | "div" | "li") && !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    text.trim().to_owned()
+}
+
+/// an anime's page on the website
+fn anime_url(route: &str) -> Url {
+    let mut url = Url::parse(SITE_URL).unwrap();
+
+    url.path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .push("anime")
+        .push(route.trim_matches('/'));
+
+    url
+}
+
 /// docs state that "0001-01-01T00:00:00Z" is a null value,
 /// therefore this treats that value as None
 fn datetime_opt<'de, D>(deserializer: D) -> Result<Option<DateTime<FixedOffset>>, D::Error>
@@ -45,3 +122,153 @@ where
 
     Ok(Some(datetime))
 }
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+    String(String),
+    Number(serde_json::Number),
+}
+
+impl From<StringOrNumber> for String {
+    fn from(value: StringOrNumber) -> Self {
+        match value {
+            StringOrNumber::String(s) => s,
+            StringOrNumber::Number(n) => n.to_string(),
+        }
+    }
+}
+
+/// ids may be sent as either a string or a number,
+/// therefore this accepts both and stores them as a string
+fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = StringOrNumber::deserialize(deserializer)?;
+
+    Ok(value.into())
+}
+
+/// optional version of [string_or_number]
+fn string_or_number_opt<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<StringOrNumber>::deserialize(deserializer)?;
+
+    Ok(value.map(Into::into))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LenientBool {
+    Bool(bool),
+    Number(i64),
+    String(String),
+}
+
+/// accepts booleans, 0/1, and "true"/"false"/"0"/"1" strings,
+/// in case the api doesn't send a json boolean
+fn lenient_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match LenientBool::deserialize(deserializer)? {
+        LenientBool::Bool(b) => Ok(b),
+        LenientBool::Number(n) => Ok(n != 0),
+        LenientBool::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(serde::de::Error::custom(format!("invalid boolean: {s}"))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Ids {
+        #[serde(deserialize_with = "string_or_number")]
+        id: String,
+        #[serde(default, deserialize_with = "string_or_number_opt")]
+        mal: Option<String>,
+    }
+
+    #[test]
+    fn string_or_number_forms() {
+        let ids = serde_json::from_str::<Ids>(r#"{ "id": "abc", "mal": "52991" }"#).unwrap();
+        assert_eq!(ids.id, "abc");
+        assert_eq!(ids.mal.as_deref(), Some("52991"));
+
+        let ids = serde_json::from_str::<Ids>(r#"{ "id": 1, "mal": 52991 }"#).unwrap();
+        assert_eq!(ids.id, "1");
+        assert_eq!(ids.mal.as_deref(), Some("52991"));
+
+        let ids = serde_json::from_str::<Ids>(r#"{ "id": 1, "mal": null }"#).unwrap();
+        assert_eq!(ids.mal, None);
+
+        let ids = serde_json::from_str::<Ids>(r#"{ "id": 1 }"#).unwrap();
+        assert_eq!(ids.mal, None);
+
+        assert!(serde_json::from_str::<Ids>(r#"{ "id": true }"#).is_err());
+    }
+
+    #[test]
+    fn lenient_bool_forms() {
+        let days = serde_json::from_str::<Days>(
+            r#"{
+                "sunday": true,
+                "monday": false,
+                "tuesday": 1,
+                "wednesday": 0,
+                "thursday": "TRUE",
+                "friday": " false ",
+                "saturday": "1"
+            }"#,
+        )
+        .unwrap();
+
+        assert!(days.sunday);
+        assert!(!days.monday);
+        assert!(days.tuesday);
+        assert!(!days.wednesday);
+        assert!(days.thursday);
+        assert!(!days.friday);
+        assert!(days.saturday);
+
+        let invalid = r#"{
+            "sunday": "yes",
+            "monday": false,
+            "tuesday": false,
+            "wednesday": false,
+            "thursday": false,
+            "friday": false,
+            "saturday": false
+        }"#;
+        assert!(serde_json::from_str::<Days>(invalid).is_err());
+    }
+
+    #[test]
+    fn anime_url_encodes_route() {
+        // the site url parses with a trailing slash, which mustn't end up as an empty segment
+        assert_eq!(
+            anime_url("sousou-no-frieren").as_str(),
+            "https://animeschedule.net/anime/sousou-no-frieren"
+        );
+        assert_eq!(
+            anime_url("/sousou-no-frieren/").as_str(),
+            "https://animeschedule.net/anime/sousou-no-frieren"
+        );
+        assert_eq!(
+            anime_url("kaguya-sama wa? #2").as_str(),
+            "https://animeschedule.net/anime/kaguya-sama%20wa%3F%20%232"
+        );
+        assert_eq!(
+            anime_url("fate/stay-night").as_str(),
+            "https://animeschedule.net/anime/fate%2Fstay-night"
+        );
+    }
+}
@@ -1,19 +1,76 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::{Deref, DerefMut, RangeInclusive},
+    str::FromStr,
+    sync::OnceLock,
+};
 
 use chrono::prelude::*;
+use reqwest::Url;
 use serde::{Deserialize, Deserializer, Serialize};
-use strum::IntoStaticStr;
+use strum::{Display, EnumString, IntoStaticStr};
 
-use super::{datetime_opt, Html};
+use super::{anime_url, datetime_opt, lenient_bool, string_or_number, string_or_number_opt, Html};
 
+/// How many anime a page of search results holds at most
+pub const ANIME_PER_PAGE: u64 = 18;
+
+/// A page of anime search results
+///
+/// The page size isn't part of the response. [`Self::total_pages()`] assumes the documented [ANIME_PER_PAGE].
+/// [`Self::page_size()`] tells the actual one where the page shows it, for [`Self::total_pages_for()`].
+/// To not depend on it at all, compare how many anime were received so far to `total_amount`, or check for an empty page.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AnimePage {
+    /// The page number.
     pub page: u64,
+    /// The total amount of anime matching the search, across all pages.
     pub total_amount: u64,
     pub anime: Vec<Anime>,
 }
 
+impl AnimePage {
+    /// How many pages the search has, at [ANIME_PER_PAGE] anime per page.
+    pub fn total_pages(&self) -> u64 {
+        self.total_pages_for(ANIME_PER_PAGE)
+    }
+
+    /// How many pages the search has, at the page size.
+    pub fn total_pages_for(&self, page_size: u64) -> u64 {
+        self.total_amount.div_ceil(page_size.max(1))
+    }
+
+    /// The page size the api used, as far as this page shows it.
+    ///
+    /// A first page with more results after it is full, so its size is the page size. Other pages can be partial,
+    /// so the default is assumed for them, unless they hold more anime than that.
+    pub fn page_size(&self, default: u64) -> u64 {
+        let len = self.anime.len() as u64;
+
+        if self.page <= 1 && len > 0 && len < self.total_amount {
+            len
+        } else {
+            default.max(len)
+        }
+    }
+
+    /// Whether there are pages after this one
+    pub fn has_next_page(&self) -> bool {
+        self.page < self.total_pages()
+    }
+
+    /// Remove anime whose id was already seen, and record the ids of the rest.
+    ///
+    /// Use this when merging multiple pages, as an anime can appear on two pages
+    /// if the search results shift between requests.
+    pub fn dedup(&mut self, seen: &mut HashSet<String>) {
+        self.anime.retain(|anime| seen.insert(anime.id.clone()));
+    }
+}
+
 /// Anime object to be used with the Anime endpoint
 /// https://animeschedule.net/api/v3/documentation/anime
 ///
@@ -23,6 +80,7 @@ pub struct AnimePage {
 #[serde(rename_all = "camelCase")]
 pub struct Anime {
     /// The unique ID.
+    #[serde(deserialize_with = "string_or_number")]
     pub id: String,
     /// The title. Separate from other names and used as a high-priority name in some cases.
     pub title: String,
@@ -101,6 +159,248 @@ pub struct Anime {
     pub websites: Websites,
 }
 
+impl Anime {
+    /// The anime's page on animeschedule.net
+    pub fn website_url(&self) -> Url {
+        anime_url(&self.route)
+    }
+
+    /// The streaming services the anime is available on, from its websites.
+    pub fn streaming_services(&self) -> Vec<StreamsQuery> {
+        self.websites
+            .available()
+            .filter_map(|(site, _)| match site {
+                Website::Stream(service) => Some(service),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the anime is currently airing, i.e. its status is [AirStatus::Ongoing].
+    ///
+    /// For whether an episode is airing right now, see [TimetableAnime::is_live].
+    pub fn is_airing_now(&self) -> bool {
+        self.status == AirStatus::Ongoing
+    }
+
+    /// The first day of the anime's release month, if both the month and year are known.
+    ///
+    /// Useful for sorting anime which have no precise premier date.
+    pub fn approx_release_date(&self) -> Option<NaiveDate> {
+        let month = self.month.as_ref()?;
+        let year = i32::try_from(self.year?).ok()?;
+
+        NaiveDate::from_ymd_opt(year, month.number() as u32, 1)
+    }
+
+    /// Compare two anime for sorting by a sorting type, so a combined set of anime can be sorted locally
+    /// (e.g. `anime.sort_by(|a, b| a.cmp_by(b, SortingType::Score))`).
+    ///
+    /// - Popularity: most popular (lowest tracked rating) first.
+    /// - Score: highest average score first.
+    /// - Alphabetic: by title, case insensitive.
+    /// - ReleaseDate: newest first, by the premier or else the approximate release date. Unknown dates are last.
+    pub fn cmp_by(&self, other: &Anime, sorting: SortingType) -> Ordering {
+        match sorting {
+            SortingType::Popularity => self.stats.tracked_rating.cmp(&other.stats.tracked_rating),
+
+            SortingType::Score => other
+                .stats
+                .average_score
+                .total_cmp(&self.stats.average_score),
+
+            SortingType::Alphabetic => self.title.to_lowercase().cmp(&other.title.to_lowercase()),
+
+            SortingType::ReleaseDate => match (self.release_date(), other.release_date()) {
+                (Some(a), Some(b)) => b.cmp(&a),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        }
+    }
+
+    /// Whether the query matches the title or any of the anime's names, including the abbreviation
+    /// and synonyms (e.g. "FMA"). Matching is case insensitive, and a name matches if it contains the query.
+    pub fn matches_name(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return false;
+        }
+
+        let matches = |name: &str| name.to_lowercase().contains(&query);
+
+        if matches(&self.title) {
+            return true;
+        }
+
+        let Some(names) = &self.names else {
+            return false;
+        };
+
+        [
+            &names.romaji,
+            &names.english,
+            &names.native,
+            &names.abbreviation,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(names.synonyms.iter().flatten())
+        .any(|name| matches(name))
+    }
+
+    /// Overlay a newer record of the same anime, e.g. a full record over a cached partial one.
+    ///
+    /// `None` values, empty strings, and empty lists in `other` keep the value of `self`.
+    /// Everything else is taken from `other`.
+    pub fn merge(&mut self, other: Anime) {
+        // destructured so a new field can't be forgotten here
+        let Anime {
+            id,
+            title,
+            route,
+            premier,
+            sub_premier,
+            dub_premier,
+            month,
+            year,
+            season,
+            delayed_timetable,
+            delayed_from,
+            delayed_until,
+            sub_delayed_timetable,
+            sub_delayed_from,
+            sub_delayed_until,
+            dub_delayed_timetable,
+            dub_delayed_from,
+            dub_delayed_until,
+            delayed_desc,
+            jpn_time,
+            sub_time,
+            dub_time,
+            description,
+            genres,
+            studios,
+            sources,
+            media_types,
+            episodes,
+            length_min,
+            status,
+            image_version_route,
+            stats,
+            days,
+            names,
+            relations,
+            websites,
+        } = other;
+
+        merge_string(&mut self.id, id);
+        merge_string(&mut self.title, title);
+        merge_string(&mut self.route, route);
+        merge_opt(&mut self.premier, premier);
+        merge_opt(&mut self.sub_premier, sub_premier);
+        merge_opt(&mut self.dub_premier, dub_premier);
+        merge_opt(&mut self.month, month);
+        merge_opt(&mut self.year, year);
+        self.season = season;
+        merge_opt(&mut self.delayed_timetable, delayed_timetable);
+        merge_opt(&mut self.delayed_from, delayed_from);
+        merge_opt(&mut self.delayed_until, delayed_until);
+        merge_opt(&mut self.sub_delayed_timetable, sub_delayed_timetable);
+        merge_opt(&mut self.sub_delayed_from, sub_delayed_from);
+        merge_opt(&mut self.sub_delayed_until, sub_delayed_until);
+        merge_opt(&mut self.dub_delayed_timetable, dub_delayed_timetable);
+        merge_opt(&mut self.dub_delayed_from, dub_delayed_from);
+        merge_opt(&mut self.dub_delayed_until, dub_delayed_until);
+        merge_opt(&mut self.delayed_desc, delayed_desc);
+        merge_opt(&mut self.jpn_time, jpn_time);
+        self.sub_time = sub_time;
+        self.dub_time = dub_time;
+        merge_string(&mut self.description.0, description.0);
+        merge_vec(&mut self.genres, genres);
+        merge_vec(&mut self.studios, studios);
+        merge_vec(&mut self.sources, sources);
+        merge_vec(&mut self.media_types, media_types);
+        merge_opt(&mut self.episodes, episodes);
+        merge_opt(&mut self.length_min, length_min);
+        self.status = status;
+        merge_string(&mut self.image_version_route, image_version_route);
+        self.stats = stats;
+        merge_opt(&mut self.days, days);
+        merge_opt(&mut self.names, names);
+        merge_opt(&mut self.relations, relations);
+        self.websites = websites;
+    }
+
+    fn release_date(&self) -> Option<NaiveDate> {
+        self.premier
+            .map(|premier| premier.date_naive())
+            .or_else(|| self.approx_release_date())
+    }
+}
+
+fn merge_opt<T>(value: &mut Option<T>, other: Option<T>) {
+    if other.is_some() {
+        *value = other;
+    }
+}
+
+fn merge_string(value: &mut String, other: String) {
+    if !other.is_empty() {
+        *value = other;
+    }
+}
+
+fn merge_vec<T>(value: &mut Vec<T>, other: Vec<T>) {
+    if !other.is_empty() {
+        *value = other;
+    }
+}
+
+/// An [Anime] which parses its description to plain text on first access, and keeps the result.
+///
+/// Useful when the same description is rendered many times. Derefs to the inner [Anime].
+#[derive(Clone, Debug)]
+pub struct ParsedAnime {
+    anime: Anime,
+    description: OnceLock<String>,
+}
+
+impl ParsedAnime {
+    pub fn new(anime: Anime) -> Self {
+        Self {
+            anime,
+            description: OnceLock::new(),
+        }
+    }
+
+    /// The description as plain text, with html tags removed and entities decoded.
+    /// Parsed only on the first call.
+    pub fn description_text(&self) -> &str {
+        self.description
+            .get_or_init(|| self.anime.description.text())
+    }
+
+    pub fn into_inner(self) -> Anime {
+        self.anime
+    }
+}
+
+impl From<Anime> for ParsedAnime {
+    fn from(anime: Anime) -> Self {
+        Self::new(anime)
+    }
+}
+
+impl Deref for ParsedAnime {
+    type Target = Anime;
+
+    fn deref(&self) -> &Self::Target {
+        &self.anime
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Season {
@@ -136,6 +436,19 @@ pub struct Category {
     pub route: String,
 }
 
+/// The category types of the categories endpoint
+#[derive(
+    Serialize, Deserialize, Copy, Clone, IntoStaticStr, Display, Debug, PartialEq, Eq, Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum CategoryType {
+    Genres,
+    Studios,
+    Sources,
+    MediaTypes,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Stats {
@@ -155,8 +468,36 @@ pub struct Stats {
     pub color_dark_mode: String,
 }
 
+impl Stats {
+    /// The RGB components of [`Self::color_light_mode`]. None if it isn't a valid `#RRGGBB` color.
+    pub fn light_color(&self) -> Option<(u8, u8, u8)> {
+        hex_color(&self.color_light_mode)
+    }
+
+    /// The RGB components of [`Self::color_dark_mode`]. None if it isn't a valid `#RRGGBB` color.
+    pub fn dark_color(&self) -> Option<(u8, u8, u8)> {
+        hex_color(&self.color_dark_mode)
+    }
+}
+
+/// parses `#RRGGBB` (the `#` is optional)
+fn hex_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.trim().trim_start_matches('#');
+
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    Some((component(0)?, component(2)?, component(4)?))
+}
+
 /// Anime airing status
-#[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq)]
+///
+/// Parsing ignores case, so `ongoing` works too.
+#[derive(Serialize, Deserialize, Clone, IntoStaticStr, Display, EnumString, Debug, PartialEq)]
+#[strum(ascii_case_insensitive)]
 pub enum AirStatus {
     Upcoming,
     Ongoing,
@@ -165,8 +506,8 @@ pub enum AirStatus {
 }
 
 /// Anime airing status
-#[derive(Serialize, Clone, IntoStaticStr, Debug, PartialEq)]
-#[serde(rename_all(serialize = "lowercase"))]
+#[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum AirStatusQuery {
     Upcoming,
     Ongoing,
@@ -175,17 +516,62 @@ pub enum AirStatusQuery {
 
 /// Whether an anime airs multiple times a week and which days specifically.
 /// Used only if it airs multiple times a week.
+///
+/// Each day is deserialized from a boolean, `0`/`1`, or a `"true"`/`"false"`/`"0"`/`"1"` string.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Days {
+    #[serde(deserialize_with = "lenient_bool")]
     pub sunday: bool,
+    #[serde(deserialize_with = "lenient_bool")]
     pub monday: bool,
+    #[serde(deserialize_with = "lenient_bool")]
     pub tuesday: bool,
+    #[serde(deserialize_with = "lenient_bool")]
     pub wednesday: bool,
+    #[serde(deserialize_with = "lenient_bool")]
     pub thursday: bool,
+    #[serde(deserialize_with = "lenient_bool")]
     pub friday: bool,
+    #[serde(deserialize_with = "lenient_bool")]
     pub saturday: bool,
 }
 
+impl Days {
+    /// The days set, from Monday to Sunday
+    pub fn weekdays(&self) -> Vec<Weekday> {
+        [
+            (Weekday::Mon, self.monday),
+            (Weekday::Tue, self.tuesday),
+            (Weekday::Wed, self.wednesday),
+            (Weekday::Thu, self.thursday),
+            (Weekday::Fri, self.friday),
+            (Weekday::Sat, self.saturday),
+            (Weekday::Sun, self.sunday),
+        ]
+        .into_iter()
+        .filter_map(|(day, set)| set.then_some(day))
+        .collect()
+    }
+
+    /// How many days are set
+    pub fn count(&self) -> usize {
+        self.weekdays().len()
+    }
+}
+
+/// The days set, abbreviated and comma separated, e.g. "Mon, Thu"
+impl std::fmt::Display for Days {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let days = self
+            .weekdays()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        f.write_str(&days.join(", "))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Names {
     pub romaji: Option<String>,
@@ -212,10 +598,15 @@ pub struct Relations {
 #[serde(rename_all = "camelCase")]
 pub struct Websites {
     pub official: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number_opt")]
     pub mal: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number_opt")]
     pub ani_list: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number_opt")]
     pub kitsu: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number_opt")]
     pub anime_planet: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number_opt")]
     pub anidb: Option<String>,
     pub crunchyroll: Option<String>,
     pub funimation: Option<String>,
@@ -225,6 +616,84 @@ pub struct Websites {
     pub hulu: Option<String>,
     pub youtube: Option<String>,
     pub netflix: Option<String>,
+    /// Sites the api sent which aren't modelled by this crate yet. [`Self::extra()`] has their urls.
+    ///
+    /// Leave it empty when building this yourself.
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl Websites {
+    /// The url of a streaming service, if the anime is available on it
+    pub fn stream(&self, service: StreamsQuery) -> Option<&str> {
+        let url = match service {
+            StreamsQuery::Crunchyroll => &self.crunchyroll,
+            StreamsQuery::Funimation => &self.funimation,
+            StreamsQuery::Wakanim => &self.wakanim,
+            StreamsQuery::Amazon => &self.amazon,
+            StreamsQuery::Hidive => &self.hidive,
+            StreamsQuery::Hulu => &self.hulu,
+            StreamsQuery::Youtube => &self.youtube,
+            StreamsQuery::Netflix => &self.netflix,
+        };
+
+        url.as_deref().filter(|url| !url.is_empty())
+    }
+
+    /// The url of a site, if the anime has a page on it
+    pub fn site(&self, site: Website) -> Option<&str> {
+        let url = match site {
+            Website::Official => &self.official,
+            Website::Mal => &self.mal,
+            Website::AniList => &self.ani_list,
+            Website::Kitsu => &self.kitsu,
+            Website::AnimePlanet => &self.anime_planet,
+            Website::Anidb => &self.anidb,
+            Website::Stream(service) => return self.stream(service),
+        };
+
+        url.as_deref().filter(|url| !url.is_empty())
+    }
+
+    /// The sites the anime has a page on, and their urls. Streaming services come last.
+    pub fn available(&self) -> impl Iterator<Item = (Website, &str)> {
+        Website::ALL
+            .into_iter()
+            .chain(StreamsQuery::ALL.map(Website::Stream))
+            .filter_map(|site| Some((site, self.site(site)?)))
+    }
+
+    /// Sites the api sent which aren't modelled by this crate yet, as (name, url).
+    pub fn extra(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.extra
+            .iter()
+            .filter_map(|(name, url)| Some((name.as_str(), url.as_str()?)))
+            .filter(|(_, url)| !url.is_empty())
+    }
+}
+
+/// A site in [Websites]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Website {
+    Official,
+    Mal,
+    AniList,
+    Kitsu,
+    AnimePlanet,
+    Anidb,
+    Stream(StreamsQuery),
+}
+
+impl Website {
+    /// Every site which isn't a streaming service
+    pub const ALL: [Website; 6] = [
+        Website::Official,
+        Website::Mal,
+        Website::AniList,
+        Website::Kitsu,
+        Website::AnimePlanet,
+        Website::Anidb,
+    ];
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -237,9 +706,54 @@ pub struct Streams {
     pub hulu: Option<String>,
     pub youtube: Option<String>,
     pub netflix: Option<String>,
+    /// Sites the api sent which aren't modelled by this crate yet. [`Self::extra()`] has their urls.
+    ///
+    /// Leave it empty when building this yourself.
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Serialize, Clone, IntoStaticStr, Debug, PartialEq)]
+impl Streams {
+    /// The url of a streaming service, if the anime is available on it
+    pub fn get(&self, service: StreamsQuery) -> Option<&str> {
+        let url = match service {
+            StreamsQuery::Crunchyroll => &self.crunchyroll,
+            StreamsQuery::Funimation => &self.funimation,
+            StreamsQuery::Wakanim => &self.wakanim,
+            StreamsQuery::Amazon => &self.amazon,
+            StreamsQuery::Hidive => &self.hidive,
+            StreamsQuery::Hulu => &self.hulu,
+            StreamsQuery::Youtube => &self.youtube,
+            StreamsQuery::Netflix => &self.netflix,
+        };
+
+        url.as_deref().filter(|url| !url.is_empty())
+    }
+
+    /// The streaming services the anime is available on, and their urls
+    pub fn available(&self) -> impl Iterator<Item = (StreamsQuery, &str)> {
+        StreamsQuery::ALL
+            .into_iter()
+            .filter_map(|service| Some((service, self.get(service)?)))
+    }
+
+    /// The first service of the preference order the anime is available on, and its url
+    pub fn preferred(&self, order: &[StreamsQuery]) -> Option<(StreamsQuery, &str)> {
+        order
+            .iter()
+            .find_map(|&service| Some((service, self.get(service)?)))
+    }
+
+    /// Sites the api sent which aren't modelled by this crate yet, as (name, url).
+    pub fn extra(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.extra
+            .iter()
+            .filter_map(|(name, url)| Some((name.as_str(), url.as_str()?)))
+            .filter(|(_, url)| !url.is_empty())
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, IntoStaticStr, Debug, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum StreamsQuery {
     Crunchyroll,
@@ -252,6 +766,20 @@ pub enum StreamsQuery {
     Netflix,
 }
 
+impl StreamsQuery {
+    /// Every streaming service
+    pub const ALL: [StreamsQuery; 8] = [
+        StreamsQuery::Crunchyroll,
+        StreamsQuery::Funimation,
+        StreamsQuery::Wakanim,
+        StreamsQuery::Amazon,
+        StreamsQuery::Hidive,
+        StreamsQuery::Hulu,
+        StreamsQuery::Youtube,
+        StreamsQuery::Netflix,
+    ];
+}
+
 #[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq)]
 pub enum Month {
     January,
@@ -268,6 +796,31 @@ pub enum Month {
     December,
 }
 
+impl Month {
+    /// Convert into chrono's month
+    pub fn as_chrono(&self) -> chrono::Month {
+        match self {
+            Month::January => chrono::Month::January,
+            Month::February => chrono::Month::February,
+            Month::March => chrono::Month::March,
+            Month::April => chrono::Month::April,
+            Month::May => chrono::Month::May,
+            Month::June => chrono::Month::June,
+            Month::July => chrono::Month::July,
+            Month::August => chrono::Month::August,
+            Month::September => chrono::Month::September,
+            Month::October => chrono::Month::October,
+            Month::November => chrono::Month::November,
+            Month::December => chrono::Month::December,
+        }
+    }
+
+    /// The month's number, from 1 (January) to 12 (December)
+    pub fn number(&self) -> u8 {
+        self.as_chrono().number_from_month() as u8
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq)]
 pub enum DelayedTimetable {
     Delayed,
@@ -275,6 +828,52 @@ pub enum DelayedTimetable {
     OnBreak,
 }
 
+/// A normalized reason for an anime's delay, parsed from the delayed text
+#[derive(Clone, Debug, PartialEq)]
+pub enum DelayReason {
+    /// The anime has been delayed.
+    Delayed,
+    /// The anime is on a break.
+    OnBreak,
+    /// The anime is on hiatus.
+    Hiatus,
+    /// Delayed text that didn't match a known reason. Contains the raw text.
+    Other(String),
+}
+
+impl DelayReason {
+    /// Parse a delayed text into a reason. Returns None if the text is empty.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        let lower = text.to_lowercase();
+
+        let reason = if lower.contains("hiatus") {
+            Self::Hiatus
+        } else if lower.contains("break") {
+            Self::OnBreak
+        } else if lower.contains("delay") {
+            Self::Delayed
+        } else {
+            Self::Other(text.to_owned())
+        };
+
+        Some(reason)
+    }
+}
+
+impl From<DelayedTimetable> for DelayReason {
+    fn from(value: DelayedTimetable) -> Self {
+        match value {
+            DelayedTimetable::Delayed => Self::Delayed,
+            DelayedTimetable::OnBreak => Self::OnBreak,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(transparent)]
 pub struct Timetables(pub Vec<TimetableAnime>);
@@ -291,6 +890,16 @@ impl DerefMut for Timetables {
     }
 }
 
+impl Timetables {
+    /// Each anime with its episode date converted to the timezone (e.g. a [chrono_tz::Tz]), for rendering local air times.
+    pub fn in_timezone<Tz: TimeZone>(&self, tz: &Tz) -> Vec<(DateTime<Tz>, &TimetableAnime)> {
+        self.0
+            .iter()
+            .map(|anime| (anime.episode_date_in(tz), anime))
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TimetableAnime {
@@ -337,6 +946,47 @@ pub struct TimetableAnime {
     pub airing_status: AiringStatus,
 }
 
+impl TimetableAnime {
+    /// The anime's page on animeschedule.net
+    pub fn website_url(&self) -> Url {
+        anime_url(&self.route)
+    }
+
+    /// The episode's date and time in the timezone (e.g. a [chrono_tz::Tz]).
+    ///
+    /// The api's `tz` parameter only changes the offset of the date it returns, so this works whether it was set or not.
+    pub fn episode_date_in<Tz: TimeZone>(&self, tz: &Tz) -> DateTime<Tz> {
+        self.episode_date.with_timezone(tz)
+    }
+
+    /// The streaming services the anime is available on, from its streams.
+    pub fn streaming_services(&self) -> Vec<StreamsQuery> {
+        self.streams
+            .available()
+            .map(|(service, _)| service)
+            .collect()
+    }
+
+    /// Whether the episode is airing right now, i.e. its airing status is [AiringStatus::Airing].
+    pub fn is_live(&self) -> bool {
+        self.airing_status == AiringStatus::Airing
+    }
+
+    /// The parsed reason for the delay, if the anime has any delayed text.
+    pub fn delay_reason(&self) -> Option<DelayReason> {
+        self.delayed_text.as_deref().and_then(DelayReason::parse)
+    }
+
+    /// The episodes airing, e.g. `5..=6` when multiple episodes air at once, otherwise just the episode number.
+    pub fn episode_range(&self) -> RangeInclusive<u64> {
+        let start = self
+            .subtracted_episode_number
+            .map_or(self.episode_number, |start| start.min(self.episode_number));
+
+        start..=self.episode_number
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum AirType {
@@ -345,7 +995,36 @@ pub enum AirType {
     Dub,
 }
 
-#[derive(Serialize, Copy, Clone, IntoStaticStr, Debug, PartialEq)]
+impl AirType {
+    /// The camelCase name the api uses. [IntoStaticStr] keeps the variant name
+    fn api_name(&self) -> &'static str {
+        match self {
+            AirType::Raw => "raw",
+            AirType::Sub => "sub",
+            AirType::Dub => "dub",
+        }
+    }
+}
+
+impl fmt::Display for AirType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.api_name())
+    }
+}
+
+/// Parses the api name, ignoring case
+impl FromStr for AirType {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [AirType::Raw, AirType::Sub, AirType::Dub]
+            .into_iter()
+            .find(|value| value.api_name().eq_ignore_ascii_case(s))
+            .ok_or(strum::ParseError::VariantNotFound)
+    }
+}
+
+#[derive(Serialize, Copy, Clone, IntoStaticStr, Debug, PartialEq, Eq, Hash)]
 #[strum(serialize_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum AirTypeQuery {
@@ -365,8 +1044,43 @@ pub enum AiringStatus {
     DelayedAir,
 }
 
+impl AiringStatus {
+    /// The kebab-case name the api uses. [IntoStaticStr] keeps the variant name
+    fn api_name(&self) -> &'static str {
+        match self {
+            AiringStatus::Airing => "airing",
+            AiringStatus::Aired => "aired",
+            AiringStatus::Unaired => "unaired",
+            AiringStatus::DelayedAir => "delayed-air",
+        }
+    }
+}
+
+impl fmt::Display for AiringStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.api_name())
+    }
+}
+
+/// Parses the api name, ignoring case
+impl FromStr for AiringStatus {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            AiringStatus::Airing,
+            AiringStatus::Aired,
+            AiringStatus::Unaired,
+            AiringStatus::DelayedAir,
+        ]
+        .into_iter()
+        .find(|value| value.api_name().eq_ignore_ascii_case(s))
+        .ok_or(strum::ParseError::VariantNotFound)
+    }
+}
+
 /// Match type
-#[derive(Serialize, Clone, IntoStaticStr, Debug, PartialEq, Default)]
+#[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum MatchType {
     /// Any searches for anime that match any of the filters.
@@ -377,7 +1091,7 @@ pub enum MatchType {
 }
 
 /// Match type
-#[derive(Serialize, Clone, IntoStaticStr, Debug, PartialEq, Default)]
+#[derive(Serialize, Deserialize, Copy, Clone, IntoStaticStr, Debug, PartialEq, Default)]
 #[serde(rename_all = "camelCase")]
 pub enum SortingType {
     #[default]
@@ -388,7 +1102,7 @@ pub enum SortingType {
 }
 
 /// Match type
-#[derive(Serialize, Clone, IntoStaticStr, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, IntoStaticStr, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SeasonQuery {
     Spring,
@@ -415,3 +1129,168 @@ where
 
     Ok(Some(datetime))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: &str = r##"{
+        "id": 1,
+        "title": "Sousou no Frieren",
+        "route": "sousou-no-frieren",
+        "premier": "2023-09-29T14:00:00Z",
+        "month": "September",
+        "year": 2023,
+        "season": { "title": "Fall 2023", "year": "2023", "season": "Fall", "route": "fall-2023" },
+        "jpnTime": "2023-09-29T14:00:00Z",
+        "subTime": "2023-09-29T15:00:00Z",
+        "dubTime": "0001-01-01T00:00:00Z",
+        "description": "<p>An elf mage looks back on her journey.</p>",
+        "genres": [{ "name": "Adventure", "route": "adventure" }],
+        "studios": [{ "name": "Madhouse", "route": "madhouse" }],
+        "sources": [{ "name": "Manga", "route": "manga" }],
+        "mediaTypes": [{ "name": "TV", "route": "tv" }],
+        "episodes": 28,
+        "lengthMin": 24,
+        "status": "Finished",
+        "imageVersionRoute": "anime/jpg/default/sousou-no-frieren.jpg",
+        "stats": {
+            "averageScore": 90.5,
+            "ratingCount": 100,
+            "trackedCount": 1000,
+            "trackedRating": 1,
+            "colorLightMode": "#000000",
+            "colorDarkMode": "#ffffff"
+        },
+        "names": { "english": "Frieren: Beyond Journey's End" },
+        "websites": { "mal": 52991 }
+    }"##;
+
+    // a partial record, as e.g. the timetables return
+    const PARTIAL: &str = r##"{
+        "id": "",
+        "title": "",
+        "route": "",
+        "season": { "title": "Fall 2023", "year": "2023", "season": "Fall", "route": "fall-2023" },
+        "jpnTime": "0001-01-01T00:00:00Z",
+        "subTime": "2023-09-29T15:00:00Z",
+        "dubTime": "0001-01-01T00:00:00Z",
+        "description": "",
+        "genres": [],
+        "studios": [],
+        "sources": [],
+        "mediaTypes": [],
+        "status": "Ongoing",
+        "imageVersionRoute": "",
+        "stats": {
+            "averageScore": 0,
+            "ratingCount": 0,
+            "trackedCount": 0,
+            "trackedRating": 0,
+            "colorLightMode": "",
+            "colorDarkMode": ""
+        },
+        "websites": {}
+    }"##;
+
+    fn anime(json: &str) -> Anime {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn merge_keeps_values_missing_from_other() {
+        let mut merged = anime(FULL);
+        merged.merge(anime(PARTIAL));
+
+        assert_eq!(merged.id, "1");
+        assert_eq!(merged.title, "Sousou no Frieren");
+        assert_eq!(merged.route, "sousou-no-frieren");
+        assert!(merged.premier.is_some());
+        assert!(merged.jpn_time.is_some());
+        assert_eq!(merged.month, Some(Month::September));
+        assert_eq!(merged.year, Some(2023));
+        assert_eq!(merged.episodes, Some(28));
+        assert_eq!(merged.length_min, Some(24));
+        assert!(!merged.description.0.is_empty());
+        assert_eq!(merged.genres[0].route, "adventure");
+        assert_eq!(merged.media_types[0].route, "tv");
+        assert!(!merged.image_version_route.is_empty());
+        assert_eq!(
+            merged.names.unwrap().english.as_deref(),
+            Some("Frieren: Beyond Journey's End")
+        );
+
+        // values which can't be missing are always taken from other
+        assert_eq!(merged.status, AirStatus::Ongoing);
+        assert_eq!(merged.stats.tracked_count, 0);
+        assert_eq!(merged.websites.mal, None);
+    }
+
+    #[test]
+    fn merge_fills_values_missing_from_self() {
+        let mut merged = anime(PARTIAL);
+        merged.merge(anime(FULL));
+
+        assert_eq!(merged.id, "1");
+        assert_eq!(merged.title, "Sousou no Frieren");
+        assert!(merged.premier.is_some());
+        assert_eq!(merged.year, Some(2023));
+        assert_eq!(merged.episodes, Some(28));
+        assert_eq!(merged.genres[0].route, "adventure");
+        assert!(merged.names.is_some());
+        assert_eq!(merged.status, AirStatus::Finished);
+        assert_eq!(merged.websites.mal.as_deref(), Some("52991"));
+    }
+
+    #[test]
+    fn merge_replaces_values_set_in_both() {
+        let mut newer = anime(FULL);
+        newer.title = "Frieren".to_owned();
+        newer.episodes = Some(12);
+        newer.genres = vec![Category {
+            name: "Fantasy".to_owned(),
+            route: "fantasy".to_owned(),
+        }];
+
+        let mut merged = anime(FULL);
+        merged.merge(newer);
+
+        assert_eq!(merged.title, "Frieren");
+        assert_eq!(merged.episodes, Some(12));
+        assert_eq!(merged.genres.len(), 1);
+        assert_eq!(merged.genres[0].route, "fantasy");
+    }
+
+    #[test]
+    fn empty_stream_urls_are_missing() {
+        let streams = Streams {
+            crunchyroll: Some(String::new()),
+            funimation: None,
+            wakanim: None,
+            amazon: None,
+            hidive: None,
+            hulu: Some("https://www.hulu.com/series/frieren".to_owned()),
+            youtube: None,
+            netflix: None,
+            extra: HashMap::from([("bilibili".to_owned(), serde_json::json!(""))]),
+        };
+
+        let websites = serde_json::from_str::<Websites>(
+            r#"{ "crunchyroll": "", "hulu": "https://www.hulu.com/series/frieren", "bilibili": "" }"#,
+        )
+        .unwrap();
+
+        assert_eq!(streams.get(StreamsQuery::Crunchyroll), None);
+        assert_eq!(websites.stream(StreamsQuery::Crunchyroll), None);
+        assert_eq!(
+            streams.get(StreamsQuery::Hulu),
+            websites.stream(StreamsQuery::Hulu)
+        );
+        assert_eq!(
+            streams.preferred(&[StreamsQuery::Crunchyroll, StreamsQuery::Hulu]),
+            Some((StreamsQuery::Hulu, "https://www.hulu.com/series/frieren"))
+        );
+        assert_eq!(streams.extra().count(), 0);
+        assert_eq!(websites.extra().count(), 0);
+    }
+}
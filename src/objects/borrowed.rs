@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use super::AirStatus;
+use crate::{api_request, errors::ApiError};
+
+/// A raw json response. Deserialize borrowed types like [AnimePageRef] from it with [`Self::parse()`].
+#[derive(Clone, Debug)]
+pub struct RawJson(pub(crate) String);
+
+impl RawJson {
+    /// Deserialize data which borrows from the response, so strings are only allocated if they contain escapes.
+    pub fn parse<'a, T: Deserialize<'a>>(&'a self) -> Result<T, ApiError> {
+        api_request::deserialize(&self.0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+/// A page of anime search results borrowing from the response text
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimePageRef<'a> {
+    /// The page number.
+    pub page: u64,
+    /// The total amount of anime matching the search, across all pages.
+    pub total_amount: u64,
+    #[serde(borrow)]
+    pub anime: Vec<AnimeRef<'a>>,
+}
+
+/// The commonly read fields of an [Anime](super::Anime), borrowing from the response text.
+///
+/// Other fields are skipped, so they cost nothing to deserialize.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimeRef<'a> {
+    /// The title. Separate from other names and used as a high-priority name in some cases.
+    #[serde(borrow)]
+    pub title: Cow<'a, str>,
+    /// The unique URL slug.
+    #[serde(borrow)]
+    pub route: Cow<'a, str>,
+    /// The description, as html.
+    #[serde(borrow)]
+    pub description: Cow<'a, str>,
+    /// The anime's poster/image URL slug.
+    #[serde(borrow)]
+    pub image_version_route: Cow<'a, str>,
+    /// The earliest year of an anime's release date.
+    pub year: Option<u64>,
+    /// The number of episodes.
+    pub episodes: Option<u64>,
+    /// The length per episode in minutes.
+    pub length_min: Option<u64>,
+    /// The airing status.
+    pub status: AirStatus,
+}
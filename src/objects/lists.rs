@@ -1,4 +1,4 @@
-use std::{collections::HashMap, ops::Deref};
+use std::{cmp::Ordering, collections::HashMap, fmt, ops::Deref, str::FromStr};
 
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -7,7 +7,7 @@ use strum::IntoStaticStr;
 
 use super::datetime_opt;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(transparent)]
 pub struct Route(pub String);
 impl Deref for Route {
@@ -26,6 +26,92 @@ pub struct UserListAnime {
     pub custom_lists: Option<Vec<CustomList>>,
 }
 
+impl UserListAnime {
+    /// Compare this list to a newer snapshot of it. Routes in the diff are sorted.
+    ///
+    /// Entries are considered changed if their status, episodes seen, scores, dates, or note differ.
+    pub fn diff(&self, other: &UserListAnime) -> ListDiff {
+        let mut diff = ListDiff::default();
+
+        for (route, anime) in &other.shows {
+            match self.shows.get(route) {
+                Some(old) if old.has_changes(anime) => diff.changed.push(route.clone()),
+                Some(_) => (),
+                None => diff.added.push(route.clone()),
+            }
+        }
+
+        diff.removed = self
+            .shows
+            .keys()
+            .filter(|route| !other.shows.contains_key(*route))
+            .cloned()
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+
+        diff
+    }
+
+    /// The episodes seen across all shows in the list
+    pub fn total_episodes_seen(&self) -> u64 {
+        self.shows.values().map(|anime| anime.episodes_seen).sum()
+    }
+
+    /// The shows sorted in ascending order, e.g. for rendering the list in a stable order.
+    /// Reverse it for descending order. Ties are sorted by route.
+    pub fn sorted_shows(&self, by: ListSortKey) -> Vec<&ListAnime> {
+        let mut shows = self.shows.values().collect::<Vec<_>>();
+
+        shows.sort_by(|a, b| {
+            let ordering = match by {
+                ListSortKey::Route => Ordering::Equal,
+                ListSortKey::Status => a.list_status.cmp(&b.list_status),
+                ListSortKey::Score => a.score().cmp(&b.score()),
+                ListSortKey::EpisodesSeen => a.episodes_seen.cmp(&b.episodes_seen),
+                ListSortKey::StartDate => a.start_date.cmp(&b.start_date),
+            };
+
+            ordering.then_with(|| a.route.cmp(&b.route))
+        });
+
+        shows
+    }
+}
+
+/// What to sort a list's shows by. See [UserListAnime::sorted_shows]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ListSortKey {
+    Route,
+    /// In the order of [ListStatus]'s variants
+    Status,
+    /// The auto score if it's used, otherwise the manual score. Unscored shows come first
+    Score,
+    EpisodesSeen,
+    /// Shows without a start date come first
+    StartDate,
+}
+
+/// The changes between two [UserListAnime] snapshots
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListDiff {
+    /// Routes which are only in the newer list.
+    pub added: Vec<Route>,
+    /// Routes which are only in the older list.
+    pub removed: Vec<Route>,
+    /// Routes in both lists whose entries changed.
+    pub changed: Vec<Route>,
+}
+
+impl ListDiff {
+    /// Whether the lists are the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ListAnime {
@@ -52,6 +138,27 @@ pub struct ListAnime {
     pub note: Option<String>,
 }
 
+impl ListAnime {
+    /// the score shown on the site
+    fn score(&self) -> Option<u8> {
+        if self.use_auto_scores {
+            self.average_auto_score
+        } else {
+            self.manual_score
+        }
+    }
+
+    fn has_changes(&self, other: &ListAnime) -> bool {
+        self.list_status != other.list_status
+            || self.episodes_seen != other.episodes_seen
+            || self.manual_score != other.manual_score
+            || self.average_auto_score != other.average_auto_score
+            || self.start_date != other.start_date
+            || self.end_date != other.end_date
+            || self.note != other.note
+    }
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
@@ -79,10 +186,13 @@ pub(crate) struct ListAnimePut {
 #[derive(Debug, Serialize, Copy, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum Action {
-    DeleteNode,
+    /// Delete the user's note. Serialized as `deleteNote`.
+    DeleteNote,
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone, IntoStaticStr, PartialEq)]
+#[derive(
+    Debug, Serialize, Deserialize, Copy, Clone, IntoStaticStr, PartialEq, Eq, PartialOrd, Ord,
+)]
 #[serde(rename_all = "kebab-case")]
 pub enum ListStatus {
     Completed,
@@ -92,6 +202,43 @@ pub enum ListStatus {
     ToWatch,
 }
 
+impl ListStatus {
+    /// The kebab-case name the api uses. [IntoStaticStr] keeps the variant name
+    fn api_name(&self) -> &'static str {
+        match self {
+            ListStatus::Completed => "completed",
+            ListStatus::Watching => "watching",
+            ListStatus::OnHold => "on-hold",
+            ListStatus::Dropped => "dropped",
+            ListStatus::ToWatch => "to-watch",
+        }
+    }
+}
+
+impl fmt::Display for ListStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.api_name())
+    }
+}
+
+/// Parses the api name, ignoring case
+impl FromStr for ListStatus {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ListStatus::Completed,
+            ListStatus::Watching,
+            ListStatus::OnHold,
+            ListStatus::Dropped,
+            ListStatus::ToWatch,
+        ]
+        .into_iter()
+        .find(|value| value.api_name().eq_ignore_ascii_case(s))
+        .ok_or(strum::ParseError::VariantNotFound)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AutoScores {
@@ -117,3 +264,124 @@ pub struct CustomList {
     /// The URL slug of the custom list.
     pub route: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_serializes_camel_case() {
+        assert_eq!(
+            serde_json::to_string(&Action::DeleteNote).unwrap(),
+            "\"deleteNote\""
+        );
+    }
+
+    fn entry(route: &str, episodes_seen: u64) -> ListAnime {
+        let score = |score_text: &str| AutoScore {
+            score_text: score_text.to_owned(),
+            score: 0,
+        };
+
+        ListAnime {
+            route: route.to_owned(),
+            list_status: ListStatus::Watching,
+            episodes_seen,
+            manual_score: None,
+            average_auto_score: None,
+            use_auto_scores: false,
+            auto_scores: AutoScores {
+                score_one: score("Story"),
+                score_two: score("Visuals"),
+                score_three: score("Sound"),
+                score_four: score("Characters"),
+            },
+            start_date: None,
+            end_date: None,
+            note: None,
+        }
+    }
+
+    fn list(shows: Vec<ListAnime>) -> UserListAnime {
+        UserListAnime {
+            user_id: "user".to_owned(),
+            shows: shows
+                .into_iter()
+                .map(|anime| (Route(anime.route.clone()), anime))
+                .collect(),
+            custom_lists: None,
+        }
+    }
+
+    fn routes(routes: &[&str]) -> Vec<Route> {
+        routes
+            .iter()
+            .map(|route| Route(route.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn diff_added_and_removed() {
+        let old = list(vec![entry("b", 1), entry("c", 1), entry("d", 1)]);
+        let new = list(vec![entry("a", 1), entry("c", 1), entry("e", 1)]);
+
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.added, routes(&["a", "e"]));
+        assert_eq!(diff.removed, routes(&["b", "d"]));
+        assert!(diff.changed.is_empty());
+
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn diff_changed_fields() {
+        let date = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap();
+
+        let changes: [fn(&mut ListAnime, DateTime<FixedOffset>); 7] = [
+            |anime, _| anime.list_status = ListStatus::Completed,
+            |anime, _| anime.episodes_seen += 1,
+            |anime, _| anime.manual_score = Some(80),
+            |anime, _| anime.average_auto_score = Some(80),
+            |anime, date| anime.start_date = Some(date),
+            |anime, date| anime.end_date = Some(date),
+            |anime, _| anime.note = Some("note".to_owned()),
+        ];
+
+        let old = list(vec![entry("a", 1)]);
+
+        for change in changes {
+            let mut anime = entry("a", 1);
+            change(&mut anime, date);
+
+            let diff = old.diff(&list(vec![anime]));
+
+            assert_eq!(diff.changed, routes(&["a"]));
+            assert!(diff.added.is_empty() && diff.removed.is_empty());
+        }
+    }
+
+    #[test]
+    fn total_episodes_seen() {
+        assert_eq!(list(vec![]).total_episodes_seen(), 0);
+
+        let list = list(vec![entry("a", 12), entry("b", 0), entry("c", 25)]);
+        assert_eq!(list.total_episodes_seen(), 37);
+    }
+
+    #[test]
+    fn list_status_names() {
+        let status = ListStatus::OnHold;
+
+        assert_eq!(<&'static str>::from(status), "OnHold");
+        assert_eq!(status.to_string(), "on-hold");
+        assert_eq!(
+            serde_json::to_string(&status).unwrap(),
+            format!("\"{status}\"")
+        );
+
+        assert_eq!("on-hold".parse::<ListStatus>(), Ok(ListStatus::OnHold));
+        assert_eq!("TO-WATCH".parse::<ListStatus>(), Ok(ListStatus::ToWatch));
+        assert!("OnHold".parse::<ListStatus>().is_err());
+    }
+}
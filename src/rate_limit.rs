@@ -1,8 +1,12 @@
-use http::HeaderMap;
+use std::{str::FromStr, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, TimeZone, Utc};
+use http::{header::RETRY_AFTER, HeaderMap};
+use serde::{Deserialize, Serialize};
 
 /// The endpoints rate limit
 #[non_exhaustive]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct RateLimit {
     /// the endpoint's limit
     pub limit: u16,
@@ -10,37 +14,163 @@ pub struct RateLimit {
     pub remaining: u16,
     /// a UNIX timestamp in seconds of when the rate limit resets
     pub reset: u64,
+    /// whether some of the rate limit headers were missing (e.g. stripped by a proxy).
+    /// missing fields are filled with defaults, so pacing decisions based on them may be unreliable
+    #[serde(default)]
+    pub partial: bool,
+    /// whether the response had no rate limit headers at all. the other fields are then only defaults which never
+    /// cause waiting, and this isn't kept as the last seen rate limit
+    #[serde(default)]
+    pub unknown: bool,
 }
 
 impl RateLimit {
+    /// Returns None only if none of the rate limit headers are present
     pub(crate) fn new(headers: &HeaderMap) -> Option<Self> {
-        let remaining = headers
-            .get("x-ratelimit-remaining")?
-            .to_str()
-            .ok()?
-            .parse()
-            .ok()?;
-
-        let reset = headers
-            .get("x-ratelimit-reset")?
-            .to_str()
-            .ok()?
-            .parse()
-            .ok()?;
-
-        let limit = headers
-            .get("x-ratelimit-limit")?
-            .to_str()
-            .ok()?
-            .parse()
-            .ok()?;
+        // the ietf draft names are fallbacks, in case the api (or a gateway) switches to them
+        let remaining = header::<u16>(headers, "x-ratelimit-remaining")
+            .or_else(|| header(headers, "ratelimit-remaining"));
+        let limit = header::<u16>(headers, "x-ratelimit-limit")
+            .or_else(|| header(headers, "ratelimit-limit"));
+        // unlike the api's header, the ietf one is in seconds from now rather than a timestamp
+        let reset = header::<u64>(headers, "x-ratelimit-reset").or_else(|| {
+            header::<u64>(headers, "ratelimit-reset")
+                .map(|delta| Utc::now().timestamp() as u64 + delta)
+        });
+
+        if remaining.is_none() && reset.is_none() && limit.is_none() {
+            return None;
+        }
+
+        let partial = remaining.is_none() || reset.is_none() || limit.is_none();
+
+        let limit = limit.unwrap_or(u16::MAX);
+        // without knowing how many are left, assume none were used
+        let remaining = remaining.unwrap_or(limit);
+        // already reset, so nobody waits on it
+        let reset = reset.unwrap_or_default();
 
         let slf = Self {
             limit,
             remaining,
             reset,
+            partial,
+            unknown: false,
         };
 
         Some(slf)
     }
+
+    /// Used when a response has no rate limit headers at all (e.g. a proxy's error page).
+    ///
+    /// Marked [unknown](Self::unknown) and [partial](Self::partial), with the same defaults as missing headers,
+    /// so it never causes any waiting.
+    pub(crate) fn unknown() -> Self {
+        Self {
+            limit: u16::MAX,
+            remaining: u16::MAX,
+            reset: 0,
+            partial: true,
+            unknown: true,
+        }
+    }
+
+    /// When the rate limit resets, in the given timezone
+    pub fn reset_local<Tz: TimeZone>(&self, tz: &Tz) -> DateTime<Tz> {
+        self.reset_at().with_timezone(tz)
+    }
+
+    /// When the rate limit resets
+    pub fn reset_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.reset as i64, 0).unwrap_or_default()
+    }
+
+    /// How long until the rate limit resets. Zero if it already did.
+    pub fn time_until_reset(&self) -> Duration {
+        let now = Utc::now().timestamp() as u64;
+        Duration::from_secs(self.reset.saturating_sub(now))
+    }
+
+    /// Whether there are no requests remaining
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Wait until the rate limit resets if there are no requests remaining
+    pub(crate) async fn wait_if_exhausted(&self) {
+        if self.is_exhausted() {
+            tokio::time::sleep(self.time_until_reset()).await;
+        }
+    }
+}
+
+/// The throttle's token bucket, shared by all endpoints like the last seen rate limit.
+///
+/// It's kept apart from the last seen rate limit, which always reports what the server sent.
+#[derive(Default)]
+pub(crate) struct Throttle {
+    // the requests left until the reset. None until a rate limit was seen
+    bucket: Mutex<Option<RateLimit>>,
+    // hands out the remaining requests one at a time
+    turn: tokio::sync::Mutex<()>,
+}
+
+impl Throttle {
+    /// Refill the bucket from a rate limit the server sent.
+    ///
+    /// Partial ones are skipped, since their missing values are made up.
+    pub fn seed(&self, limit: RateLimit) {
+        if !limit.partial {
+            *self.bucket.lock().unwrap() = Some(limit);
+        }
+    }
+
+    /// The requests left in the bucket
+    #[cfg(test)]
+    pub fn remaining(&self) -> Option<u16> {
+        self.bucket.lock().unwrap().map(|limit| limit.remaining)
+    }
+
+    /// Take one of the remaining requests, waiting for the reset if there are none left.
+    ///
+    /// The bucket is counted down before the response arrives, so concurrent requests
+    /// together stay within the budget.
+    pub async fn take(&self) {
+        let _guard = self.turn.lock().await;
+
+        // nothing to go by until the first response
+        let Some(mut limit) = *self.bucket.lock().unwrap() else {
+            return;
+        };
+
+        if limit.is_exhausted() {
+            tokio::time::sleep(limit.time_until_reset()).await;
+            // a new window started, so the full limit is available again
+            limit.remaining = limit.limit;
+        }
+
+        limit.remaining = limit.remaining.saturating_sub(1);
+        *self.bucket.lock().unwrap() = Some(limit);
+    }
+}
+
+/// The Retry-After header, which is either in seconds or an http date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    // http dates are a subset of rfc 2822
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = (date.with_timezone(&Utc) - Utc::now())
+        .to_std()
+        .unwrap_or_default();
+
+    Some(wait)
+}
+
+fn header<T: FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
 }
@@ -0,0 +1,39 @@
+use http::{
+    header::{CONTENT_LENGTH, ETAG},
+    HeaderMap, StatusCode,
+};
+
+use crate::rate_limit::RateLimit;
+
+/// Metadata of a response, read from its status and headers
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    /// the response's status code
+    pub status: StatusCode,
+    /// the rate limit, if any of its headers were present
+    pub rate_limit: Option<RateLimit>,
+    /// the raw ETag header value, including any `W/` prefix and quotes
+    pub etag: Option<String>,
+    /// the id the server or a proxy assigned to the request, for reporting issues
+    pub request_id: Option<String>,
+    /// the size of the body in bytes, if the server sent it
+    pub content_length: Option<u64>,
+}
+
+impl ResponseMeta {
+    pub(crate) fn new(status: StatusCode, headers: &HeaderMap) -> Self {
+        Self {
+            status,
+            rate_limit: RateLimit::new(headers),
+            etag: header(headers, ETAG.as_str()),
+            request_id: header(headers, "x-request-id"),
+            content_length: header(headers, CONTENT_LENGTH.as_str())
+                .and_then(|len| len.parse().ok()),
+        }
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    Some(headers.get(name)?.to_str().ok()?.to_owned())
+}
@@ -0,0 +1,83 @@
+//! A minimal http server for tests, which answers a single request
+
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+/// A request the server received
+pub struct Received {
+    /// the request line and headers
+    pub head: String,
+    pub body: String,
+}
+
+impl Received {
+    /// The value of a header. The name is case insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// A raw http response with the status (e.g. `204 No Content`), headers and body
+pub fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let headers = headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}\r\n"))
+        .collect::<String>();
+
+    format!(
+        "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Answer the next request with the raw response. Returns the server's url, and a handle resolving to the request
+pub async fn serve_once(response: String) -> (Url, JoinHandle<Received>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+    let handle = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut buf = Vec::new();
+        let mut chunk = [0; 1024];
+
+        // the head, then as much of the body as the content length says
+        let received = loop {
+            let read = stream.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..read]);
+
+            let request = String::from_utf8_lossy(&buf);
+            if let Some((head, body)) = request.split_once("\r\n\r\n") {
+                let received = Received {
+                    head: head.to_owned(),
+                    body: body.to_owned(),
+                };
+
+                let len = received
+                    .header("content-length")
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or(0);
+
+                if received.body.len() >= len || read == 0 {
+                    break received;
+                }
+            }
+
+            assert_ne!(read, 0, "the connection closed before the request was read");
+        };
+
+        stream.write_all(response.as_bytes()).await.unwrap();
+        _ = stream.shutdown().await;
+
+        received
+    });
+
+    (url, handle)
+}
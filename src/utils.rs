@@ -8,6 +8,54 @@ impl IsJson for String {
     }
 }
 
+/// How many characters of a non-json body are kept in an error
+const MAX_SUMMARY_LEN: usize = 200;
+
+/// Summarize a non-json response body so errors stay readable (e.g. a cdn's html error page).
+///
+/// Html is reduced to its title, anything else to its first line. The content type is included if known.
+pub fn summarize_body(body: &str, content_type: Option<&str>) -> String {
+    let is_html =
+        content_type.is_some_and(|ty| ty.contains("html")) || body.trim_start().starts_with('<');
+
+    let summary = is_html
+        .then(|| html_title(body))
+        .flatten()
+        .or_else(|| body.lines().map(str::trim).find(|line| !line.is_empty()))
+        .unwrap_or_default();
+
+    let mut chars = summary.chars();
+    let mut summary = chars.by_ref().take(MAX_SUMMARY_LEN).collect::<String>();
+    if chars.next().is_some() {
+        summary.push('…');
+    }
+
+    match content_type {
+        Some(ty) => format!("[{ty}] {summary}"),
+        None => summary,
+    }
+}
+
+/// The first `max` characters of the string.
+///
+/// Unlike [String::truncate] this counts characters rather than bytes, so it never splits a multibyte character.
+pub fn truncate_chars(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+/// the contents of an html document's title tag
+fn html_title(html: &str) -> Option<&str> {
+    // ascii lowercasing keeps the byte offsets the same
+    let lower = html.to_ascii_lowercase();
+
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+
+    let title = html[start..end].trim();
+    (!title.is_empty()).then_some(title)
+}
+
 pub struct LazyLock<T, F = fn() -> T> {
     data: ::std::sync::OnceLock<T>,
     f: F,